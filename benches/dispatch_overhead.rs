@@ -6,8 +6,13 @@
 //!
 //! The goal is to determine if listing many features in the target string
 //! adds measurable overhead to the runtime dispatch.
+//!
+//! The size sweep compares the scalar fallback, tiered dispatch, and
+//! `#[multiversed(min_len = ...)]` across input lengths, to show where dispatching
+//! to a SIMD tier starts to pay off.
 
-use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use criterion::{BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main};
+use multiversed::multiversed;
 
 // ============================================================================
 // Test functions with different target string sizes
@@ -67,6 +72,32 @@ fn sum_baseline(data: &[f32]) -> f32 {
     data.iter().sum()
 }
 
+// ============================================================================
+// Size sweep functions (elementwise, so SIMD tiers actually vectorize)
+// ============================================================================
+
+fn scale_add_scalar(a: &[f32], b: &[f32], out: &mut [f32]) {
+    for ((o, x), y) in out.iter_mut().zip(a).zip(b) {
+        *o = x * 1.5 + y;
+    }
+}
+
+/// Default tiers (x86-64-v4x, x86-64-v3, arm64-v2), always dispatched
+#[multiversed]
+fn scale_add_tiered(a: &[f32], b: &[f32], out: &mut [f32]) {
+    for ((o, x), y) in out.iter_mut().zip(a).zip(b) {
+        *o = x * 1.5 + y;
+    }
+}
+
+/// Default tiers, fallback below 64 elements
+#[multiversed(min_len = 64, len = out)]
+fn scale_add_min_len(a: &[f32], b: &[f32], out: &mut [f32]) {
+    for ((o, x), y) in out.iter_mut().zip(a).zip(b) {
+        *o = x * 1.5 + y;
+    }
+}
+
 // ============================================================================
 // Benchmarks
 // ============================================================================
@@ -203,5 +234,34 @@ fn bench_repeated_dispatch(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_dispatch_overhead, bench_repeated_dispatch);
+/// Sweep input sizes to find where tiered dispatch overtakes the scalar fallback
+fn bench_size_sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("size_sweep");
+
+    for len in [1usize, 4, 8, 16, 32, 64, 128, 256, 1024, 4096] {
+        let a: Vec<f32> = (0..len).map(|i| i as f32).collect();
+        let b: Vec<f32> = (0..len).map(|i| (i * 2) as f32).collect();
+        let mut out = vec![0.0f32; len];
+        group.throughput(Throughput::Elements(len as u64));
+
+        group.bench_with_input(BenchmarkId::new("scalar", len), &len, |bench, _| {
+            bench.iter(|| scale_add_scalar(black_box(&a), black_box(&b), black_box(&mut out)))
+        });
+        group.bench_with_input(BenchmarkId::new("tiered", len), &len, |bench, _| {
+            bench.iter(|| scale_add_tiered(black_box(&a), black_box(&b), black_box(&mut out)))
+        });
+        group.bench_with_input(BenchmarkId::new("min_len_64", len), &len, |bench, _| {
+            bench.iter(|| scale_add_min_len(black_box(&a), black_box(&b), black_box(&mut out)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_dispatch_overhead,
+    bench_repeated_dispatch,
+    bench_size_sweep
+);
 criterion_main!(benches);
@@ -0,0 +1,104 @@
+#![deny(unsafe_code)]
+// The build.rs example needs its `fn main`.
+#![allow(clippy::needless_doctest_main)]
+
+//! Build-script helper for [`multiversed`].
+//!
+//! Tells a build script which presets the compile target's baseline already meets, from
+//! the `CARGO_CFG_TARGET_ARCH` and `CARGO_CFG_TARGET_FEATURE` variables cargo sets for
+//! build scripts. A build with `-C target-cpu=x86-64-v3` meets `x86-64-v2` and
+//! `x86-64-v3`; a default x86_64 build meets none.
+//!
+//! ```no_run
+//! // build.rs
+//! fn main() {
+//!     multiversed_build::emit_preset_cfgs();
+//! }
+//! ```
+//!
+//! ```
+//! // src/lib.rs
+//! #[cfg(multiversed_baseline_x86_64_v3)]
+//! fn sum(data: &[f32]) -> f32 {
+//!     // AVX2 and FMA are enabled everywhere; no dispatch needed
+//!     data.iter().sum()
+//! }
+//! ```
+//!
+//! The cfgs carry canonical preset names only: `x86-64-v4x` sets
+//! `multiversed_baseline_x86_64_v4x`, and there is no cfg for the alias `x86-64-v4-modern`.
+//!
+//! [`multiversed`]: https://docs.rs/multiversed
+
+use std::env;
+
+pub use multiversed_rt::{Arch, Preset};
+
+/// The presets whose every feature the compile target enables, least capable first.
+///
+/// Reads `CARGO_CFG_TARGET_ARCH` and `CARGO_CFG_TARGET_FEATURE`, so it only gives
+/// meaningful results inside a build script. Empty if they are unset.
+pub fn baseline_presets() -> Vec<Preset> {
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let features = env::var("CARGO_CFG_TARGET_FEATURE").unwrap_or_default();
+    presets_met(&arch, &features)
+}
+
+/// The cfg a met preset sets: `multiversed_baseline_` and its name with `-` as `_`.
+pub fn cfg_name(preset: Preset) -> String {
+    format!("multiversed_baseline_{}", preset.name().replace('-', "_"))
+}
+
+/// Emit a `cargo::rustc-cfg` for each of [`baseline_presets`], and a
+/// `cargo::rustc-check-cfg` declaring every preset's cfg so none trips
+/// `unexpected_cfgs`.
+///
+/// Call it from `build.rs`.
+pub fn emit_preset_cfgs() {
+    for preset in Preset::ALL {
+        println!("cargo::rustc-check-cfg=cfg({})", cfg_name(preset));
+    }
+    for preset in baseline_presets() {
+        println!("cargo::rustc-cfg={}", cfg_name(preset));
+    }
+}
+
+/// The presets of `arch` (a `target_arch` name) covered by `features`, a
+/// comma-separated `target_feature` list.
+fn presets_met(arch: &str, features: &str) -> Vec<Preset> {
+    let enabled: Vec<&str> = features.split(',').collect();
+    Preset::ALL
+        .into_iter()
+        .filter(|preset| preset.arch().target_arch() == arch)
+        .filter(|preset| preset.features().iter().all(|f| enabled.contains(f)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_follow_the_feature_list() {
+        let v3 = Preset::X86_64V3.features().join(",");
+        assert_eq!(
+            presets_met("x86_64", &v3),
+            [Preset::X86_64V2, Preset::X86_64V3]
+        );
+        assert_eq!(presets_met("x86_64", "fxsr,sse,sse2"), []);
+        assert_eq!(presets_met("aarch64", &v3), []);
+        assert_eq!(presets_met("wasm32", "simd128"), [Preset::Wasm128]);
+
+        let arm = format!("{},sve", Preset::Arm64V2.features().join(","));
+        assert_eq!(presets_met("aarch64", &arm), [Preset::Arm64V2]);
+    }
+
+    #[test]
+    fn cfg_names() {
+        assert_eq!(cfg_name(Preset::X86_64V3), "multiversed_baseline_x86_64_v3");
+        assert_eq!(
+            cfg_name(Preset::Wasm128),
+            "multiversed_baseline_wasm32_simd128"
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! First-call autotuning for `#[multiversed(autotune)]`.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Ident, ItemFn};
+
+use crate::dispatch::{
+    CloneOptions, Forward, Target, arch_indices, call_counters, count_call, detect_fn,
+    multiversioned_fn, registration, state_path, state_vis, target_labels, trace_resolved,
+};
+use crate::{Autotune, private_path};
+
+/// Generate the nested copies, the dispatcher state and the selection logic for
+/// `autotune`.
+///
+/// Index 0 is `fallback`. Every tier gets a copy multiversioned for that tier alone.
+/// The first call times every copy whose features the host has, through `forward`'s
+/// arguments or the sample function, and the returned expression then calls the
+/// winner. The dispatcher state is returned for the companion module if there is
+/// one, and is among the items otherwise.
+pub(crate) fn autotune_dispatch(
+    func: &ItemFn,
+    forward: &Forward,
+    fallback: &Ident,
+    autotune: &Autotune,
+    x86_targets: &[String],
+    aarch64_targets: &[String],
+    options: CloneOptions,
+) -> syn::Result<(Vec<TokenStream>, TokenStream, Option<TokenStream>)> {
+    let sig = &forward.outer_sig;
+    if let Some(asyncness) = &sig.asyncness {
+        return Err(syn::Error::new(
+            asyncness.span,
+            "`autotune` is not supported on async functions",
+        ));
+    }
+    // The tuned tier is one static, shared by every instantiation.
+    if let Some(param) = sig
+        .generics
+        .params
+        .iter()
+        .find(|param| !matches!(param, syn::GenericParam::Lifetime(_)))
+    {
+        return Err(syn::Error::new_spanned(
+            param,
+            "`autotune` needs a function without type or const parameters, since every instantiation would share one tuned tier",
+        ));
+    }
+    if let Autotune::FirstCall = autotune
+        && let Some(ty) = forward.arg_types.iter().find(|ty| !repeatable(ty))
+    {
+        return Err(syn::Error::new_spanned(
+            ty,
+            "`autotune` reruns the first call to measure it, so arguments must be shared references or primitive values; use `autotune = sample` to measure on inputs of your own",
+        ));
+    }
+
+    let tiers: Vec<(&String, bool)> = x86_targets
+        .iter()
+        .map(|target| (target, true))
+        .chain(aarch64_targets.iter().map(|target| (target, false)))
+        .collect();
+    let parsed = tiers
+        .iter()
+        .map(|(target, _)| Target::parse(target))
+        .collect::<syn::Result<Vec<_>>>()?;
+    // Each tier copy dispatches on its own, but only this dispatcher is registered.
+    let tier_options = CloneOptions {
+        register: None,
+        module: None,
+        ..options
+    };
+
+    let mut items = Vec::new();
+    let mut indices = Vec::new();
+    let mut cfgs = Vec::new();
+    let mut detected = Vec::new();
+    let mut calls = Vec::new();
+    for (((target, x86), parsed), index) in tiers.iter().zip(&parsed).zip(arch_indices(&parsed)) {
+        let copy = Forward::copy(func, &format!("__multiversed_tier_{index}"));
+        let (x86, aarch64): (&[String], &[String]) = if *x86 {
+            (std::slice::from_ref(*target), &[])
+        } else {
+            (&[], std::slice::from_ref(*target))
+        };
+        let cfg = parsed.arch_cfg();
+        calls.push(forward.call(&copy.sig.ident));
+        let copy = multiversioned_fn(copy, x86, aarch64, tier_options)?;
+        items.push(quote! {
+            #[cfg(#cfg)]
+            #copy
+        });
+        indices.push(index);
+        cfgs.push(cfg);
+        detected.push(parsed.detected(options.krate));
+    }
+
+    let private = private_path(options.krate);
+    let arg_names = &forward.arg_names;
+    let arg_types = &forward.arg_types;
+    let call_fallback = forward.call(fallback);
+    let measure_fn = quote! {
+        let __multiversed_measure = |#(#arg_names: #arg_types),*| -> usize {
+            let mut best = (
+                0usize,
+                #private::time(|| {
+                    ::core::hint::black_box(#call_fallback);
+                }),
+            );
+            #(
+                #[cfg(#cfgs)]
+                if #detected {
+                    let time = #private::time(|| {
+                        ::core::hint::black_box(#calls);
+                    });
+                    if time < best.1 {
+                        best = (#indices, time);
+                    }
+                }
+            )*
+            best.0
+        };
+    };
+
+    let measure = match autotune {
+        Autotune::FirstCall => quote! { __multiversed_measure(#(#arg_names),*) },
+        Autotune::Sample(sample) => quote! {{
+            let mut choice = 0;
+            #sample(&mut |#(#arg_names: #arg_types),*| {
+                choice = __multiversed_measure(#(#arg_names),*);
+            });
+            choice
+        }},
+    };
+    let ident = &sig.ident;
+    let labels = target_labels(&parsed, options);
+    let registration = registration(&parsed, &quote::format_ident!("TUNED"), false, options);
+    let counters = call_counters(&parsed, options);
+    let vis = state_vis(options);
+    // Until the first call is tuned, report the tier plain dispatch would pick.
+    let select = options.module.map(|_| {
+        let detect = detect_fn(&parsed, options);
+        quote! {
+            #vis fn __multiversed_select() -> usize {
+                #detect
+
+                match TUNED.load(::core::sync::atomic::Ordering::Relaxed) {
+                    usize::MAX => detect(),
+                    tuned => tuned,
+                }
+            }
+        }
+    });
+    let state = quote! {
+        #labels
+
+        // usize::MAX = not tuned yet, otherwise the index of the fastest copy
+        #vis static TUNED: ::core::sync::atomic::AtomicUsize =
+            ::core::sync::atomic::AtomicUsize::new(usize::MAX);
+
+        #counters
+        #select
+        #registration
+    };
+    let path = state_path(options);
+    let count = count_call(quote! { tuned }, &parsed, options);
+    let trace = trace_resolved(&parsed, quote! { tuned }, true, false, options);
+    let dispatch = quote! {
+        use ::core::sync::atomic::Ordering;
+        let mut tuned = #path TUNED.load(Ordering::Relaxed);
+        if tuned == usize::MAX {
+            #measure_fn
+            let detected = |index: usize| match index {
+                0 => true,
+                #(#[cfg(#cfgs)] #indices => #detected,)*
+                _ => false,
+            };
+            tuned = #private::autotune(
+                concat!(module_path!(), "::", stringify!(#ident)),
+                #path __MULTIVERSED_TARGETS,
+                &detected,
+                &mut || #measure,
+            );
+            #path TUNED.store(tuned, Ordering::Relaxed);
+            #trace
+        }
+        #count
+        match tuned {
+            #(#[cfg(#cfgs)] #indices => #calls,)*
+            _ => #call_fallback,
+        }
+    };
+
+    let state = if options.module.is_some() {
+        Some(state)
+    } else {
+        items.push(state);
+        None
+    };
+    Ok((items, dispatch, state))
+}
+
+/// Whether rerunning a call with an argument of type `ty` leaves the caller's data
+/// alone: shared references to types without interior mutability, primitive values,
+/// and tuples and arrays of those.
+///
+/// Interior mutability is recognized by the standard type names, which is as far as a
+/// macro can see.
+fn repeatable(ty: &syn::Type) -> bool {
+    use syn::Type;
+
+    const PRIMITIVES: &[&str] = &[
+        "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16",
+        "u32", "u64", "u128", "usize",
+    ];
+
+    match ty {
+        Type::Reference(reference) => {
+            reference.mutability.is_none() && !interior_mutable(&reference.elem)
+        }
+        Type::Path(path) => {
+            path.qself.is_none()
+                && path
+                    .path
+                    .get_ident()
+                    .is_some_and(|ident| PRIMITIVES.iter().any(|primitive| ident == primitive))
+        }
+        Type::Tuple(tuple) => tuple.elems.iter().all(repeatable),
+        Type::Array(array) => repeatable(&array.elem),
+        Type::Paren(paren) => repeatable(&paren.elem),
+        Type::Group(group) => repeatable(&group.elem),
+        _ => false,
+    }
+}
+
+/// Whether `ty` names a standard interior-mutable type anywhere in it.
+fn interior_mutable(ty: &syn::Type) -> bool {
+    use syn::visit::Visit;
+
+    struct Finder(bool);
+
+    impl<'ast> Visit<'ast> for Finder {
+        fn visit_path_segment(&mut self, segment: &'ast syn::PathSegment) {
+            let name = segment.ident.to_string();
+            self.0 |= name.starts_with("Atomic")
+                || [
+                    "Cell",
+                    "RefCell",
+                    "UnsafeCell",
+                    "OnceCell",
+                    "OnceLock",
+                    "LazyCell",
+                    "LazyLock",
+                    "Mutex",
+                    "RwLock",
+                ]
+                .contains(&name.as_str());
+            syn::visit::visit_path_segment(self, segment);
+        }
+
+        fn visit_type_reference(&mut self, reference: &'ast syn::TypeReference) {
+            self.0 |= reference.mutability.is_some();
+            syn::visit::visit_type_reference(self, reference);
+        }
+    }
+
+    let mut finder = Finder(false);
+    finder.visit_type(ty);
+    finder.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repeatable_str(ty: &str) -> bool {
+        repeatable(&syn::parse_str(ty).unwrap())
+    }
+
+    #[test]
+    fn shared_references_and_primitives_repeat() {
+        for ty in [
+            "&[f32]",
+            "&str",
+            "&'a Vec<u8>",
+            "f32",
+            "usize",
+            "(f32, &[u8])",
+            "[u8; 4]",
+        ] {
+            assert!(repeatable_str(ty), "{ty}");
+        }
+    }
+
+    #[test]
+    fn mutable_owned_and_interior_mutable_do_not() {
+        for ty in [
+            "&mut [f32]",
+            "Vec<f32>",
+            "String",
+            "T",
+            "impl Fn()",
+            "*const f32",
+            "(f32, &mut [u8])",
+            "&Cell<u32>",
+            "&[std::sync::atomic::AtomicUsize]",
+            "&std::sync::Mutex<Vec<u8>>",
+            "&&mut [f32]",
+        ] {
+            assert!(!repeatable_str(ty), "{ty}");
+        }
+    }
+}
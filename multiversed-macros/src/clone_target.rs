@@ -0,0 +1,154 @@
+//! `target::selected_target!` and `target::match_target!`: what the running copy of a
+//! `#[multiversed]` body is compiled for.
+//!
+//! Each copy of the body starts with its own `__MULTIVERSED_TARGET` constant and
+//! `__multiversed_match_target!` macro, which the two macros expand to. They are items
+//! of the body's block rather than of a module, so `crate = "..."` paths resolve in
+//! them as in the rest of the body.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{Expr, Lit, LitStr, Pat, Stmt, parse_quote};
+use target_features::{Architecture, Feature};
+
+use crate::dispatch::Target;
+use crate::private_path;
+
+/// The items a copy of the body starts with, for a clone compiled for `target`, or the
+/// fallback if `None`.
+pub(crate) fn target_items(target: Option<&Target>, krate: &syn::Path) -> Vec<Stmt> {
+    let private = private_path(krate);
+    let (selected, features) = match target {
+        Some(target) => (target.target_string(), target.features.as_slice()),
+        None => (String::new(), &[][..]),
+    };
+    vec![
+        parse_quote! {
+            #[allow(dead_code)]
+            const __MULTIVERSED_TARGET: #krate::target::Target =
+                #private::target_features::CURRENT_TARGET #(.with_feature_str(#features))*;
+        },
+        parse_quote! {
+            #[allow(unused_macros)]
+            macro_rules! __multiversed_match_target {
+                ($($arms:tt)*) => { #private::match_target! { #selected $($arms)* } };
+            }
+        },
+    ]
+}
+
+/// The features `arch+feature+...` enables, including the ones they imply.
+fn enabled_features(span: Span, arch: &str, features: &[&str]) -> syn::Result<Vec<&'static str>> {
+    let architecture = Architecture::from_str(arch);
+    let mut target = target_features::Target::new(architecture);
+    for feature in features {
+        let feature = Feature::new(architecture, feature).map_err(|_| {
+            syn::Error::new(
+                span,
+                format!("unknown target feature `{feature}` for {arch}"),
+            )
+        })?;
+        target = target.with_feature(feature);
+    }
+    Ok(target.features().map(|feature| feature.name()).collect())
+}
+
+/// Check that every feature of a target is one rustc knows for its architecture.
+pub(crate) fn check_features(target: &Target) -> syn::Result<()> {
+    let features: Vec<&str> = target.features.iter().map(String::as_str).collect();
+    enabled_features(Span::call_site(), &target.arch, &features).map(|_| ())
+}
+
+/// `__match_target!`'s input: the target string of the copy it is expanded in (empty
+/// for the fallback), then the arms of `match_target!`.
+pub(crate) struct MatchTarget {
+    selected: LitStr,
+    arms: Vec<(LitStr, Expr)>,
+    default: Option<Expr>,
+}
+
+impl Parse for MatchTarget {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let selected = input.parse()?;
+        let mut arms = Vec::new();
+        let mut default = None;
+        while !input.is_empty() {
+            let arm: syn::Arm = input.parse()?;
+            if let Some((if_token, _)) = arm.guard {
+                return Err(syn::Error::new(if_token.span, "unexpected guard"));
+            }
+            let cases = match arm.pat {
+                Pat::Or(or) => or.cases.into_iter().collect(),
+                pat => vec![pat],
+            };
+            for case in cases {
+                match case {
+                    Pat::Lit(syn::ExprLit {
+                        lit: Lit::Str(target),
+                        ..
+                    }) => arms.push((target, (*arm.body).clone())),
+                    Pat::Wild(_) if default.is_none() => default = Some((*arm.body).clone()),
+                    pat => return Err(syn::Error::new(pat.span(), "expected a target string")),
+                }
+            }
+            if default.is_some() && !input.is_empty() {
+                return Err(input.error("unreachable targets after `_`"));
+            }
+        }
+        Ok(MatchTarget {
+            selected,
+            arms,
+            default,
+        })
+    }
+}
+
+impl MatchTarget {
+    /// The body of the first arm whose target the copy is compiled for, as `cfg`s.
+    ///
+    /// An arm matches if the copy is compiled for its architecture and every feature,
+    /// either as a clone or with the build's `target_feature`s.
+    pub(crate) fn expand(&self) -> syn::Result<TokenStream> {
+        let selected = self.selected.value();
+        let (selected_arch, selected) = match selected.split_once('+') {
+            Some((arch, features)) => {
+                let features: Vec<&str> = features.split('+').collect();
+                (
+                    arch,
+                    enabled_features(self.selected.span(), arch, &features)?,
+                )
+            }
+            None => ("", Vec::new()),
+        };
+
+        let mut exprs = Vec::new();
+        let mut earlier = Vec::new();
+        for (target, expr) in &self.arms {
+            let value = target.value();
+            let mut parts = value.split('+');
+            let arch = parts.next().unwrap_or_default();
+            let features: Vec<&str> = parts.collect();
+            let needed = enabled_features(target.span(), arch, &features)?
+                .into_iter()
+                .filter(|feature| arch != selected_arch || !selected.contains(feature));
+            let cfg = quote! { all(target_arch = #arch #(, target_feature = #needed)*) };
+            exprs.push(quote! {
+                #[cfg(all(#cfg, not(any(#(#earlier),*))))]
+                { #expr }
+            });
+            earlier.push(cfg);
+        }
+        let default = match &self.default {
+            Some(expr) => quote! { #expr },
+            None => syn::Error::new(Span::call_site(), "no target matches; add a `_` arm")
+                .to_compile_error(),
+        };
+        Ok(quote! {{
+            #(#exprs)*
+            #[cfg(not(any(#(#earlier),*)))]
+            { #default }
+        }})
+    }
+}
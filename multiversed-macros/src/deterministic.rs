@@ -0,0 +1,132 @@
+//! Deterministic floating point for `#[multiversed(deterministic_fp)]`.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::ItemFn;
+
+use crate::private_path;
+
+/// Remove FMA from an x86 target string for `deterministic_fp`.
+///
+/// rustc never contracts `a * b + c` and `mul_add` is correctly rounded either way, so
+/// FMA only changes results through code that picks a fused path by the clone's
+/// features: `match_target!`, archmage tokens, FMA intrinsics. Every AVX-512 feature
+/// implies FMA in rustc, so targets using any of them cannot exclude it (`None`).
+/// aarch64 targets are left alone: NEON has no separate FMA feature.
+pub(crate) fn without_fma(target: &str) -> Option<String> {
+    if target.split('+').any(|f| f.starts_with("avx512")) {
+        return None;
+    }
+    Some(
+        target
+            .split('+')
+            .filter(|f| *f != "fma")
+            .collect::<Vec<_>>()
+            .join("+"),
+    )
+}
+
+/// Remove repeated targets, keeping the first occurrence.
+pub(crate) fn dedup(targets: &mut Vec<String>) {
+    let mut seen = Vec::new();
+    targets.retain(|t| {
+        if seen.contains(t) {
+            false
+        } else {
+            seen.push(t.clone());
+            true
+        }
+    });
+}
+
+/// Check whether a method or function name is a fused multiply-add.
+///
+/// Covers `mul_add`, the x86 `_mm*_f[n]madd*`/`_mm*_f[n]msub*` intrinsics, and the
+/// aarch64 `vfma*`/`vfms*` intrinsics.
+fn is_fused_op(name: &str) -> bool {
+    name == "mul_add"
+        || (name.starts_with("_mm")
+            && ["_fmadd", "_fmsub", "_fnmadd", "_fnmsub"]
+                .iter()
+                .any(|op| name.contains(op)))
+        || name.starts_with("vfma")
+        || name.starts_with("vfms")
+}
+
+/// Call sites of fused multiply-adds in the body.
+///
+/// Calls inside macro invocations are not visible to the scan.
+fn fused_ops(func: &ItemFn) -> Vec<Span> {
+    use syn::spanned::Spanned;
+    use syn::visit::Visit;
+
+    struct FusedOps(Vec<Span>);
+
+    impl<'ast> Visit<'ast> for FusedOps {
+        fn visit_expr_method_call(&mut self, call: &'ast syn::ExprMethodCall) {
+            if is_fused_op(&call.method.to_string()) {
+                self.0.push(call.method.span());
+            }
+            syn::visit::visit_expr_method_call(self, call);
+        }
+
+        fn visit_expr_call(&mut self, call: &'ast syn::ExprCall) {
+            if let syn::Expr::Path(path) = &*call.func
+                && let Some(segment) = path.path.segments.last()
+                && is_fused_op(&segment.ident.to_string())
+            {
+                self.0.push(path.span());
+            }
+            syn::visit::visit_expr_call(self, call);
+        }
+    }
+
+    let mut visitor = FusedOps(Vec::new());
+    visitor.visit_block(&func.block);
+    visitor.0
+}
+
+/// A use of the deprecated constant `name` in `__private::deterministic_fp`, spanned at
+/// `span` so the warning points there.
+///
+/// Stable proc macros cannot emit warnings directly. The uses go in the outer function
+/// only, not in the clones, so each warning is reported once.
+fn warning(name: &str, span: Span, krate: &syn::Path) -> TokenStream {
+    let private = private_path(krate);
+    let name = syn::Ident::new(name, span);
+    quote! { const _: () = #private::deterministic_fp::#name; }
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect::<TokenStream>()
+}
+
+/// One warning per fused multiply-add call in the body.
+pub(crate) fn fused_op_warnings(func: &ItemFn, krate: &syn::Path) -> Vec<TokenStream> {
+    fused_ops(func)
+        .into_iter()
+        .map(|span| warning("FUSED_MULTIPLY_ADD", span, krate))
+        .collect()
+}
+
+/// The warning that AVX-512 tiers from the cargo feature defaults were dropped.
+pub(crate) fn dropped_tiers_warning(krate: &syn::Path) -> TokenStream {
+    warning("AVX512_TIERS_DROPPED", Span::call_site(), krate)
+}
+
+/// Error for AVX-512 tiers listed explicitly with `deterministic_fp`.
+pub(crate) fn dropped_tiers_error(labels: &[String]) -> syn::Error {
+    let labels = labels
+        .iter()
+        .map(|label| format!("`{label}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    syn::Error::new(
+        Span::call_site(),
+        format!(
+            "`deterministic_fp` can't keep {labels}: AVX-512 implies FMA; remove them from the targets"
+        ),
+    )
+}
@@ -0,0 +1,965 @@
+//! Per-target clones and runtime dispatch.
+//!
+//! A multiversioned function becomes an outer function with the original signature
+//! that holds, as nested items, one clone of the body per target, a plain fallback
+//! clone, and a dispatcher that detects the best supported target once and calls its
+//! clone from then on.
+//!
+//! Each clone is a copy of the body with its target's features enabled by
+//! `#[target_feature]`, and starts with the items `target::selected_target!` and
+//! `target::match_target!` read.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{ToTokens, format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Ident, ItemFn, Stmt, parse_quote};
+
+use multiversed_rt::presets::PRESETS;
+
+use crate::clone_target::{check_features, target_items};
+use crate::deterministic::without_fma;
+use crate::expose::tier_fns;
+use crate::private_path;
+use crate::tokens::matched_token_bindings;
+
+/// Settings shared by every clone of one function.
+#[derive(Clone, Copy)]
+pub(crate) struct CloneOptions<'a> {
+    /// Path of the `multiversed` crate: `::multiversed` unless `crate = "..."` is given.
+    pub(crate) krate: &'a syn::Path,
+    /// Name of the user's function, which the clones are named after.
+    pub(crate) name: &'a Ident,
+    /// Bind an archmage token named `token` in each clone.
+    pub(crate) tokens: bool,
+    /// Microarchitectures on which the AVX-512 tiers are skipped.
+    pub(crate) avoid_on: &'a [String],
+    /// Name to list the dispatcher under in the registry, if it should be listed.
+    pub(crate) register: Option<&'a Ident>,
+    /// Companion module holding the dispatcher's state, which is otherwise kept inside
+    /// the function.
+    pub(crate) module: Option<&'a Ident>,
+    /// Make each clone callable as `name::tiers::<suffix>`, for `expose`.
+    pub(crate) expose: bool,
+}
+
+// ============================================================================
+// Targets
+// ============================================================================
+
+/// A resolved target string: an architecture followed by `+`-separated features.
+pub(crate) struct Target {
+    pub(crate) arch: String,
+    pub(crate) features: Vec<String>,
+}
+
+impl Target {
+    pub(crate) fn parse(target: &str) -> syn::Result<Self> {
+        let mut parts = target.split('+');
+        let arch = parts.next().unwrap_or_default().to_string();
+        if arch.contains('/') {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                format!("CPU-named target `{target}` is not supported; list its features instead"),
+            ));
+        }
+        let features: Vec<String> = parts.map(String::from).collect();
+        if features.is_empty() || features.iter().any(String::is_empty) {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                format!("target `{target}` must list features as `arch+feature+...`"),
+            ));
+        }
+        let target = Target { arch, features };
+        check_features(&target)?;
+        Ok(target)
+    }
+
+    /// The target string, `arch+feature+...`.
+    pub(crate) fn target_string(&self) -> String {
+        format!("{}+{}", self.arch, self.features.join("+"))
+    }
+
+    /// The preset name for the target, or the target string if it isn't a preset.
+    ///
+    /// A preset with `fma` removed by `deterministic_fp` keeps the preset's name.
+    pub(crate) fn label(&self) -> String {
+        let target = self.target_string();
+        PRESETS
+            .iter()
+            .find(|def| def.target_string() == target)
+            .or_else(|| {
+                PRESETS
+                    .iter()
+                    .find(|def| without_fma(&def.target_string()).as_deref() == Some(&*target))
+            })
+            .map_or(target, |def| def.name.to_string())
+    }
+
+    /// The clone name suffix: the preset name with `_` for `-`, e.g. `x86_64_v4x`.
+    ///
+    /// Raw targets with up to three features spell them out (`x86_64_avx2_fma`), and
+    /// others get the architecture and a hash of the target string (`x86_64_t1b2c3d4e`).
+    pub(crate) fn suffix(&self) -> String {
+        let label = self.label();
+        let suffix = if !label.contains('+') {
+            label
+        } else if self.features.len() <= 3 {
+            format!("{}_{}", self.arch, self.features.join("_"))
+        } else {
+            // FNV-1a, so the tag is the same on every build and compiler.
+            let hash = label.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+                (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+            });
+            format!("{}_t{hash:08x}", self.arch)
+        };
+        suffix.replace(['-', '.'], "_")
+    }
+
+    /// Whether the target uses 512-bit vectors, which `avoid_on` skips.
+    pub(crate) fn is_avx512(&self) -> bool {
+        self.features.iter().any(|f| f.starts_with("avx512"))
+    }
+
+    /// `cfg` predicate selecting the target's architecture.
+    pub(crate) fn arch_cfg(&self) -> TokenStream {
+        let arch = &self.arch;
+        quote! { target_arch = #arch }
+    }
+
+    /// `cfg` predicate that holds when the program is compiled with every feature.
+    pub(crate) fn enabled_cfg(&self) -> TokenStream {
+        let arch = &self.arch;
+        let features = &self.features;
+        quote! { all(target_arch = #arch, #(target_feature = #features),*) }
+    }
+
+    /// Expression checking that the host supports every feature.
+    ///
+    /// Only valid where [`arch_cfg`](Self::arch_cfg) holds. `krate` is the path of
+    /// `multiversed`, as for [`CloneOptions::krate`].
+    pub(crate) fn detected(&self, krate: &syn::Path) -> TokenStream {
+        let enabled = self.enabled_cfg();
+        if self.arch == "wasm32" {
+            // No runtime detection: only what the program was compiled with.
+            return quote! { cfg!(#enabled) };
+        }
+        let checks = self
+            .features
+            .iter()
+            .map(|feature| self.feature_detected(feature, krate));
+        quote! { (cfg!(#enabled) || (true #(&& #checks)*)) }
+    }
+
+    /// `(feature, detected)` pairs for each feature, under the same conditions as
+    /// [`detected`](Self::detected).
+    pub(crate) fn feature_checks(&self, krate: &syn::Path) -> TokenStream {
+        let checks = self.features.iter().map(|feature| {
+            if self.arch == "wasm32" {
+                quote! { (#feature, cfg!(target_feature = #feature)) }
+            } else {
+                let detected = self.feature_detected(feature, krate);
+                quote! { (#feature, cfg!(target_feature = #feature) || #detected) }
+            }
+        });
+        quote! { &[#(#checks),*] }
+    }
+
+    /// Runtime check of one feature. Features of the architecture's presets are read
+    /// from the shared capability snapshot, others detected on their own.
+    fn feature_detected(&self, feature: &str, krate: &syn::Path) -> TokenStream {
+        let private = private_path(krate);
+        let in_presets = PRESETS
+            .iter()
+            .any(|def| def.arch.target_arch() == self.arch && def.features().contains(&feature));
+        if in_presets {
+            quote! { #private::has_feature(#feature) }
+        } else if self.arch == "aarch64" {
+            quote! { #private::is_aarch64_feature_detected!(#feature) }
+        } else {
+            quote! { #private::is_x86_feature_detected!(#feature) }
+        }
+    }
+}
+
+// ============================================================================
+// Registry entries
+// ============================================================================
+
+/// Index of each target among the targets of its architecture, starting at 1.
+///
+/// Only one architecture's clones are compiled, so its targets can number their clones
+/// and label them from 1 without gaps. 0 is the fallback.
+pub(crate) fn arch_indices(targets: &[Target]) -> Vec<usize> {
+    targets
+        .iter()
+        .enumerate()
+        .map(|(i, target)| {
+            1 + targets[..i]
+                .iter()
+                .filter(|t| t.arch == target.arch)
+                .count()
+        })
+        .collect()
+}
+
+/// `__MULTIVERSED_TARGETS`: the labels of the targets for the architecture being
+/// compiled, in index order.
+pub(crate) fn target_labels(targets: &[Target], options: CloneOptions) -> TokenStream {
+    let vis = state_vis(options);
+    let arches = arches(targets);
+    let per_arch = arches.iter().map(|arch| {
+        let labels = targets
+            .iter()
+            .filter(|target| target.arch == *arch)
+            .map(Target::label);
+        quote! {
+            #[cfg(target_arch = #arch)]
+            #vis const __MULTIVERSED_TARGETS: &[&str] = &[#(#labels),*];
+        }
+    });
+    quote! {
+        #(#per_arch)*
+        #[cfg(not(any(#(target_arch = #arches),*)))]
+        #vis const __MULTIVERSED_TARGETS: &[&str] = &[];
+    }
+}
+
+/// The architectures of `targets`, in order of first appearance.
+pub(crate) fn arches(targets: &[Target]) -> Vec<&str> {
+    let mut arches: Vec<&str> = Vec::new();
+    for target in targets {
+        if !arches.contains(&target.arch.as_str()) {
+            arches.push(&target.arch);
+        }
+    }
+    arches
+}
+
+/// `cfg` predicate for the architectures of `targets`, where registry entries exist.
+fn arches_cfg(targets: &[Target]) -> TokenStream {
+    let arches = targets.iter().map(|target| &target.arch);
+    quote! { any(#(target_arch = #arches),*) }
+}
+
+/// Whether the dispatcher counts its calls: with the `stats` feature, for registered
+/// dispatchers.
+fn counts_calls(options: CloneOptions) -> bool {
+    cfg!(feature = "stats") && options.register.is_some()
+}
+
+/// `__MULTIVERSED_CALLS`, the dispatcher's call counters, if it counts calls.
+///
+/// Index 0 counts the fallback and `i` the target `__MULTIVERSED_TARGETS[i - 1]`.
+pub(crate) fn call_counters(targets: &[Target], options: CloneOptions) -> Option<TokenStream> {
+    if !counts_calls(options) {
+        return None;
+    }
+    let arches = arches_cfg(targets);
+    let vis = state_vis(options);
+    Some(quote! {
+        #[cfg(#arches)]
+        #vis static __MULTIVERSED_CALLS: [::core::sync::atomic::AtomicU64; __MULTIVERSED_TARGETS.len() + 1] =
+            [const { ::core::sync::atomic::AtomicU64::new(0) }; __MULTIVERSED_TARGETS.len() + 1];
+    })
+}
+
+/// Statement counting a call of the clone at `index`, if the dispatcher counts calls.
+pub(crate) fn count_call(
+    index: TokenStream,
+    targets: &[Target],
+    options: CloneOptions,
+) -> Option<TokenStream> {
+    if !counts_calls(options) {
+        return None;
+    }
+    let arches = arches_cfg(targets);
+    let state = state_path(options);
+    Some(quote! {
+        #[cfg(#arches)]
+        #state __MULTIVERSED_CALLS[#index].fetch_add(1, ::core::sync::atomic::Ordering::Relaxed);
+    })
+}
+
+/// The path of the function `name` as a string, from the dispatcher state if `in_state`
+/// and from its body otherwise.
+fn function_path(name: &Ident, in_state: bool, options: CloneOptions) -> TokenStream {
+    // The companion module has the function's path.
+    if in_state && options.module.is_some() {
+        quote! { module_path!() }
+    } else {
+        quote! { concat!(module_path!(), "::", stringify!(#name)) }
+    }
+}
+
+/// A registry entry for `options.register`, whose dispatcher caches its choice in the
+/// `AtomicUsize` static `selected`, indexing `__MULTIVERSED_TARGETS`.
+///
+/// With `resolves`, `__multiversed_select()` resolves the choice for `init_all()`, and
+/// `redetect()` resets it to `__MULTIVERSED_UNRESOLVED`.
+pub(crate) fn registration(
+    targets: &[Target],
+    selected: &Ident,
+    resolves: bool,
+    options: CloneOptions,
+) -> Option<TokenStream> {
+    let name = options.register?;
+    let private = private_path(options.krate);
+    let arches = arches_cfg(targets);
+    let calls = if counts_calls(options) {
+        quote! { &__MULTIVERSED_CALLS }
+    } else {
+        quote! { &[] }
+    };
+    let path = function_path(name, true, options);
+    let (resolve, unresolved) = if resolves {
+        (
+            quote! { ::core::option::Option::Some(__multiversed_select) },
+            quote! { __MULTIVERSED_UNRESOLVED },
+        )
+    } else {
+        (
+            quote! { ::core::option::Option::None },
+            quote! { usize::MAX },
+        )
+    };
+    Some(quote! {
+        #[cfg(#arches)]
+        #[#private::linkme::distributed_slice(#private::FUNCTIONS)]
+        #[linkme(crate = #private::linkme)]
+        static __MULTIVERSED_REGISTERED: #private::Function =
+            #private::Function::new(
+                #path,
+                __MULTIVERSED_TARGETS,
+                &#selected,
+                #calls,
+                #resolve,
+                #unresolved,
+            );
+    })
+}
+
+// ============================================================================
+// Dispatch tracing (trace-dispatch)
+// ============================================================================
+
+/// Statement logging that the registered dispatcher resolved to the clone at
+/// `selected`, with the `trace-dispatch` feature.
+///
+/// `tuned` tells autotuning from detection. The statement sits in the dispatcher
+/// state if `in_state`, and in the function body otherwise.
+pub(crate) fn trace_resolved(
+    targets: &[Target],
+    selected: TokenStream,
+    tuned: bool,
+    in_state: bool,
+    options: CloneOptions,
+) -> Option<TokenStream> {
+    if !cfg!(feature = "trace-dispatch") {
+        return None;
+    }
+    let name = options.register?;
+    let private = private_path(options.krate);
+    let arches = arches_cfg(targets);
+    let path = function_path(name, in_state, options);
+    let state = if in_state {
+        quote! {}
+    } else {
+        state_path(options)
+    };
+    // In `__MULTIVERSED_TARGETS` order: only the compiled architecture's targets.
+    let features = targets.iter().map(|target| {
+        let arch = target.arch_cfg();
+        let checks = target.feature_checks(options.krate);
+        quote! { #[cfg(#arch)] #checks }
+    });
+    Some(quote! {
+        #[cfg(#arches)]
+        #private::trace_dispatch(
+            #path,
+            #tuned,
+            #selected,
+            #state __MULTIVERSED_TARGETS,
+            &[#(#features),*],
+        );
+    })
+}
+
+// ============================================================================
+// Dispatcher state
+// ============================================================================
+
+/// Visibility of dispatcher state the function refers to: `pub(super)` in the
+/// companion module, private inside the function.
+pub(crate) fn state_vis(options: CloneOptions) -> TokenStream {
+    if options.module.is_some() {
+        quote! { pub(super) }
+    } else {
+        quote! {}
+    }
+}
+
+/// Path prefix for dispatcher state, as seen from inside the function.
+pub(crate) fn state_path(options: CloneOptions) -> TokenStream {
+    match options.module {
+        Some(module) => quote! { #module:: },
+        None => quote! {},
+    }
+}
+
+/// `fn detect() -> usize`: the index of the first target the host supports, or 0.
+pub(crate) fn detect_fn(targets: &[Target], options: CloneOptions) -> TokenStream {
+    let private = private_path(options.krate);
+    let avoid_on = options.avoid_on;
+    let checks = targets
+        .iter()
+        .zip(arch_indices(targets))
+        .map(|(target, index)| {
+            let arch = target.arch_cfg();
+            let detected = target.detected(options.krate);
+            let avoided = (target.is_avx512() && !avoid_on.is_empty())
+                .then(|| quote! { !#private::on_microarch(&[#(#avoid_on),*]) && });
+            quote! {
+                #[cfg(#arch)]
+                {
+                    if #avoided #detected {
+                        return #index;
+                    }
+                }
+            }
+        });
+    quote! {
+        #[cold]
+        fn detect() -> usize {
+            #(#checks)*
+            0
+        }
+    }
+}
+
+/// The state of a tiered dispatcher: its target labels, the cached choice and
+/// `__multiversed_select()`, which detects the choice on first use.
+pub(crate) fn dispatcher_state(targets: &[Target], options: CloneOptions) -> TokenStream {
+    // The best target is compiled in, so there is nothing to detect; unless it is one
+    // `avoid_on` may skip.
+    let best_enabled = if targets[0].is_avx512() && !options.avoid_on.is_empty() {
+        quote! { any() }
+    } else {
+        targets[0].enabled_cfg()
+    };
+    let labels = target_labels(targets, options);
+    let detect = detect_fn(targets, options);
+    let registration = registration(
+        targets,
+        &format_ident!("__MULTIVERSED_SELECTED"),
+        true,
+        options,
+    );
+    let counters = call_counters(targets, options);
+    let trace = trace_resolved(targets, quote! { selected }, false, true, options);
+    let vis = state_vis(options);
+    quote! {
+        #labels
+        #counters
+
+        // usize::MAX = not detected yet, otherwise the index of the selected clone
+        const __MULTIVERSED_UNRESOLVED: usize = if cfg!(#best_enabled) { 1 } else { usize::MAX };
+        static __MULTIVERSED_SELECTED: ::core::sync::atomic::AtomicUsize =
+            ::core::sync::atomic::AtomicUsize::new(__MULTIVERSED_UNRESOLVED);
+
+        #[inline(always)]
+        #vis fn __multiversed_select() -> usize {
+            #detect
+
+            if cfg!(#best_enabled) {
+                return 1;
+            }
+
+            use ::core::sync::atomic::Ordering;
+            let selected = __MULTIVERSED_SELECTED.load(Ordering::Relaxed);
+            if selected != usize::MAX {
+                return selected;
+            }
+            let selected = detect();
+            __MULTIVERSED_SELECTED.store(selected, Ordering::Relaxed);
+            #trace
+            selected
+        }
+
+        #registration
+    }
+}
+
+/// The state of a function without targets for any architecture: it always runs the
+/// baseline.
+pub(crate) fn baseline_state() -> TokenStream {
+    quote! {
+        const __MULTIVERSED_TARGETS: &[&str] = &[];
+
+        pub(super) fn __multiversed_select() -> usize {
+            0
+        }
+    }
+}
+
+/// The companion module `func::...`, reporting how `func` dispatches.
+///
+/// `state` defines `__MULTIVERSED_TARGETS` and `__multiversed_select()`. `tiers` is the
+/// `expose` module, if requested.
+pub(crate) fn companion_module(
+    func: &ItemFn,
+    state: TokenStream,
+    tiers: Option<TokenStream>,
+) -> TokenStream {
+    let vis = &func.vis;
+    let name = &func.sig.ident;
+    let doc = format!("Runtime dispatch of `{name}`, generated by `#[multiversed]`.");
+    let selected_doc = format!(
+        "The target `{name}` dispatches to on this CPU, or `\"baseline\"` for the fallback."
+    );
+    let available_doc = format!(
+        "The targets `{name}` has clones for on this architecture, highest priority first."
+    );
+    quote! {
+        #[doc = #doc]
+        #[allow(dead_code)]
+        #vis mod #name {
+            #state
+
+            #[doc = #selected_doc]
+            ///
+            /// Targets given as presets are reported by their canonical names, raw target
+            /// strings as written.
+            pub fn selected_target() -> &'static str {
+                match __multiversed_select() {
+                    0 => "baseline",
+                    i => __MULTIVERSED_TARGETS[i - 1],
+                }
+            }
+
+            #[doc = #available_doc]
+            pub fn available_targets() -> &'static [&'static str] {
+                __MULTIVERSED_TARGETS
+            }
+
+            #tiers
+        }
+    }
+}
+
+// ============================================================================
+// Generated documentation
+// ============================================================================
+
+/// Doc lines appended to a multiversioned function's docs, listing its targets by
+/// architecture: `*Runtime-dispatched: x86-64-v4x, x86-64-v3 (x86_64); arm64-v2 (aarch64)*`.
+pub(crate) fn dispatch_doc(targets: &[Target]) -> Vec<syn::Attribute> {
+    let mut arches: Vec<(&str, Vec<String>)> = Vec::new();
+    for target in targets {
+        match arches.iter_mut().find(|(arch, _)| *arch == target.arch) {
+            Some((_, labels)) => labels.push(target.label()),
+            None => arches.push((&target.arch, vec![target.label()])),
+        }
+    }
+    let tiers: Vec<String> = arches
+        .iter()
+        .map(|(arch, labels)| format!("{} ({arch})", labels.join(", ")))
+        .collect();
+    let line = format!("*Runtime-dispatched: {}*", tiers.join("; "));
+    vec![
+        parse_quote! { #[doc = ""] },
+        parse_quote! { #[doc = #line] },
+    ]
+}
+
+// ============================================================================
+// Forwarding from an outer function to nested copies
+// ============================================================================
+
+/// The outer function of a multiversioned function and how it forwards to nested copies.
+///
+/// Arguments with non-identifier patterns get plain names on the outer function; the
+/// nested copies keep the original patterns.
+pub(crate) struct Forward {
+    pub(crate) outer_sig: syn::Signature,
+    pub(crate) arg_names: Vec<Ident>,
+    pub(crate) arg_types: Vec<syn::Type>,
+    turbofish: TokenStream,
+}
+
+impl Forward {
+    pub(crate) fn new(func: &ItemFn, len: Option<&Ident>) -> syn::Result<Self> {
+        use syn::spanned::Spanned;
+        use syn::{FnArg, GenericParam, Pat};
+
+        let sig = &func.sig;
+        if let Some(receiver) = sig.receiver() {
+            return Err(syn::Error::new(
+                receiver.span(),
+                "`#[multiversed]` is not supported on methods with a `self` receiver",
+            ));
+        }
+
+        let mut outer_sig = sig.clone();
+        let mut arg_names = Vec::new();
+        let mut arg_types = Vec::new();
+        for (i, input) in outer_sig.inputs.iter_mut().enumerate() {
+            if let FnArg::Typed(arg) = input {
+                let name = match &*arg.pat {
+                    Pat::Ident(pat) if pat.subpat.is_none() => pat.ident.clone(),
+                    _ => Ident::new(&format!("__multiversed_arg_{i}"), arg.pat.span()),
+                };
+                *arg.pat = parse_quote! { #name };
+                arg_names.push(name);
+                arg_types.push((*arg.ty).clone());
+            }
+        }
+
+        if let Some(len) = len
+            && !arg_names.contains(len)
+        {
+            return Err(syn::Error::new(
+                len.span(),
+                format!("`len = {len}` must name an argument of `{}`", sig.ident),
+            ));
+        }
+
+        let generic_args: Vec<&Ident> = sig
+            .generics
+            .params
+            .iter()
+            .filter_map(|param| match param {
+                GenericParam::Type(ty) => Some(&ty.ident),
+                GenericParam::Const(c) => Some(&c.ident),
+                GenericParam::Lifetime(_) => None,
+            })
+            .collect();
+        let turbofish = if generic_args.is_empty() {
+            quote! {}
+        } else {
+            quote! { ::<#(#generic_args),*> }
+        };
+
+        Ok(Forward {
+            outer_sig,
+            arg_names,
+            arg_types,
+            turbofish,
+        })
+    }
+
+    /// A nested copy of `func` named `name`, without the outer function's attributes.
+    ///
+    /// Clone names are documented, so perf annotations can rely on them.
+    pub(crate) fn copy(func: &ItemFn, name: &str) -> ItemFn {
+        let mut copy = func.clone();
+        // Clone names like `resize_h__x86_64_v3` aren't snake case.
+        copy.attrs = vec![parse_quote! { #[allow(non_snake_case)] }];
+        copy.vis = syn::Visibility::Inherited;
+        copy.sig.ident = Ident::new(name, func.sig.ident.span());
+        copy
+    }
+
+    fn call_expr(&self, callee: &impl ToTokens) -> TokenStream {
+        let turbofish = &self.turbofish;
+        let arg_names = &self.arg_names;
+        let maybe_await = self.outer_sig.asyncness.map(|_| quote! { .await });
+        quote! { #callee #turbofish(#(#arg_names),*) #maybe_await }
+    }
+
+    /// Call the nested copy `callee` with the outer function's arguments.
+    pub(crate) fn call(&self, callee: &impl ToTokens) -> TokenStream {
+        let call = self.call_expr(callee);
+        if self.outer_sig.unsafety.is_some() {
+            quote! { unsafe { #call } }
+        } else {
+            call
+        }
+    }
+
+    /// Call the nested copy `callee` inside an `unsafe` block, for `#[target_feature]` clones.
+    pub(crate) fn call_unsafe(&self, callee: &impl ToTokens) -> TokenStream {
+        let call = self.call_expr(callee);
+        quote! { unsafe { #call } }
+    }
+
+    /// Build the outer function.
+    ///
+    /// It evaluates the body of the first of `branches` whose condition holds, and
+    /// `dispatch` otherwise.
+    pub(crate) fn wrap(
+        &self,
+        func: &ItemFn,
+        items: Vec<TokenStream>,
+        branches: Vec<(TokenStream, TokenStream)>,
+        dispatch: TokenStream,
+    ) -> TokenStream {
+        let branches = branches
+            .iter()
+            .map(|(condition, body)| quote! { if #condition { #body } else });
+
+        let attrs = &func.attrs;
+        let vis = &func.vis;
+        let outer_sig = &self.outer_sig;
+        quote! {
+            #(#attrs)*
+            #vis #outer_sig {
+                #(#items)*
+
+                #(#branches)* {
+                    #dispatch
+                }
+            }
+        }
+    }
+}
+
+/// Insert `items` at the start of the generated outer function's body.
+pub(crate) fn with_items(func: TokenStream, items: &[TokenStream]) -> syn::Result<TokenStream> {
+    if items.is_empty() {
+        return Ok(func);
+    }
+    let mut func: ItemFn = syn::parse2(func)?;
+    insert_items(&mut func, items)?;
+    Ok(quote! { #func })
+}
+
+/// Insert `items` at the start of `func`'s body.
+pub(crate) fn insert_items(func: &mut ItemFn, items: &[TokenStream]) -> syn::Result<()> {
+    let items = items
+        .iter()
+        .map(|item| syn::parse2(item.clone()))
+        .collect::<syn::Result<Vec<Stmt>>>()?;
+    func.block.stmts.splice(0..0, items);
+    Ok(())
+}
+
+// ============================================================================
+// Multiversioned functions
+// ============================================================================
+
+/// Multiversion `func` for the given targets, highest priority first.
+///
+/// Without targets the function is returned as is, apart from the `tokens` binding and
+/// the items of [`target_items`].
+pub(crate) fn multiversioned_fn(
+    mut func: ItemFn,
+    x86_targets: &[String],
+    aarch64_targets: &[String],
+    options: CloneOptions,
+) -> syn::Result<TokenStream> {
+    let targets = x86_targets
+        .iter()
+        .chain(aarch64_targets)
+        .map(|target| Target::parse(target))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let arches = arches(&targets);
+
+    if options.tokens {
+        func.block
+            .stmts
+            .splice(0..0, matched_token_bindings(&arches, options.krate));
+    }
+    if targets.is_empty() {
+        func.block
+            .stmts
+            .splice(0..0, target_items(None, options.krate));
+        return Ok(quote! { #func });
+    }
+
+    let forward = Forward::new(&func, None)?;
+
+    // `r#` can't start a longer name.
+    let name = options.name.unraw();
+    let mut fallback = Forward::copy(&func, &format!("{name}__baseline"));
+    fallback.attrs.push(parse_quote! { #[inline(always)] });
+    fallback
+        .block
+        .stmts
+        .splice(0..0, target_items(None, options.krate));
+    let call_fallback = forward.call(&fallback.sig.ident);
+    let mut items = vec![quote! { #fallback }];
+
+    for target in &targets {
+        let mut clone = Forward::copy(&func, &format!("{name}__{}", target.suffix()));
+        let arch = target.arch_cfg();
+        let features = target.features.join(",");
+        clone.attrs.push(parse_quote! { #[cfg(#arch)] });
+        clone
+            .attrs
+            .push(parse_quote! { #[target_feature(enable = #features)] });
+        clone
+            .block
+            .stmts
+            .splice(0..0, target_items(Some(target), options.krate));
+        items.push(quote! { #clone });
+    }
+
+    let arms = targets
+        .iter()
+        .zip(arch_indices(&targets))
+        .map(|(target, index)| {
+            let arch = target.arch_cfg();
+            let clone = format_ident!("{name}__{}", target.suffix());
+            let call = forward.call_unsafe(&clone);
+            quote! {
+                #[cfg(#arch)]
+                #index => #call,
+            }
+        });
+
+    // In the companion module if there is one; the caller generates it there.
+    if options.module.is_none() {
+        items.push(dispatcher_state(&targets, options));
+    }
+    if options.expose {
+        items.push(tier_fns(&targets, &forward, options));
+    }
+    let state = state_path(options);
+    let count = count_call(quote! { selected }, &targets, options);
+
+    let mut dispatch_sig = forward.outer_sig.clone();
+    dispatch_sig.ident = format_ident!("__multiversed_dispatch");
+    let inline = dispatch_sig
+        .asyncness
+        .is_none()
+        .then(|| quote! { #[inline(always)] });
+    items.push(quote! {
+        #[allow(unsafe_code)]
+        #inline
+        #dispatch_sig {
+            // SAFETY: `__multiversed_select` only picks a clone after detecting (or
+            // compiling with) every feature it enables.
+            let selected = #state __multiversed_select();
+            #count
+            match selected {
+                #(#arms)*
+                _ => #call_fallback,
+            }
+        }
+    });
+
+    let call_dispatch = forward.call(&dispatch_sig.ident);
+    Ok(forward.wrap(&func, items, Vec::new(), call_dispatch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::Item;
+
+    const DISPATCHED: &str = "*Runtime-dispatched: x86-64-v3 (x86_64); arm64-v2 (aarch64)*";
+
+    fn targets(names: &[&str]) -> Vec<Target> {
+        names
+            .iter()
+            .map(|name| Target::parse(&crate::resolve_target(name).unwrap()).unwrap())
+            .collect()
+    }
+
+    fn doc_lines(attrs: &[syn::Attribute]) -> Vec<String> {
+        attrs
+            .iter()
+            .filter_map(|attr| match &attr.meta {
+                syn::Meta::NameValue(doc) if doc.path.is_ident("doc") => match &doc.value {
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(line),
+                        ..
+                    }) => Some(line.value()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The expansion of `#[multiversed(attr)]` on a function summing `data`.
+    fn expand(attr: &str, docs: &str) -> String {
+        let args = syn::parse_str(attr).unwrap();
+        let func = syn::parse_str(&format!(
+            "{docs}\npub fn sum(data: &[f32]) -> f32 {{ data.iter().sum() }}"
+        ))
+        .unwrap();
+        crate::multiversed_impl(args, func).unwrap().to_string()
+    }
+
+    /// The outer function of an expansion, which may be followed by its companion module.
+    fn outer_fn(expansion: &str) -> ItemFn {
+        let file: syn::File = syn::parse_str(expansion).unwrap();
+        file.items
+            .into_iter()
+            .find_map(|item| match item {
+                Item::Fn(func) => Some(func),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn lists_tiers_by_architecture() {
+        let doc = dispatch_doc(&targets(&["x86-64-v4x", "x86-64-v3", "arm64-v2"]));
+        assert_eq!(
+            doc_lines(&doc),
+            [
+                "",
+                "*Runtime-dispatched: x86-64-v4x, x86-64-v3 (x86_64); arm64-v2 (aarch64)*"
+            ]
+        );
+        let doc = dispatch_doc(&targets(&["arm64-v3", "arm64-v2"]));
+        assert_eq!(
+            doc_lines(&doc)[1],
+            "*Runtime-dispatched: arm64-v3, arm64-v2 (aarch64)*"
+        );
+    }
+
+    #[test]
+    fn names_tiers_like_the_attribute() {
+        // Aliases by their canonical name, raw targets as written.
+        let doc = dispatch_doc(&targets(&[
+            "x86-64-v4-modern",
+            "x86_64+avx+popcnt",
+            "arm64",
+        ]));
+        assert_eq!(
+            doc_lines(&doc)[1],
+            "*Runtime-dispatched: x86-64-v4x, x86_64+avx+popcnt (x86_64); arm64-v2 (aarch64)*"
+        );
+        // A preset reduced by `deterministic_fp` keeps its name.
+        let v3 = crate::resolve_target("x86-64-v3").unwrap();
+        let doc = dispatch_doc(&[Target::parse(&without_fma(&v3).unwrap()).unwrap()]);
+        assert_eq!(
+            doc_lines(&doc)[1],
+            "*Runtime-dispatched: x86-64-v3 (x86_64)*"
+        );
+    }
+
+    #[test]
+    fn documents_the_outer_function_once() {
+        for attr in [
+            r#""x86-64-v3", "arm64-v2""#,
+            r#""x86-64-v3", "arm64-v2", min_len = 4, len = data"#,
+            r#""x86-64-v3", "arm64-v2", autotune"#,
+            r#""x86-64-v3", "arm64-v2", min_len = 4, len = data, autotune, companion"#,
+        ] {
+            let expansion = expand(attr, "/// Sums.");
+            let func = outer_fn(&expansion);
+            assert_eq!(doc_lines(&func.attrs), [" Sums.", "", DISPATCHED], "{attr}");
+            // The nested copies and clones stay undocumented.
+            assert_eq!(expansion.matches("Runtime-dispatched").count(), 1, "{attr}");
+        }
+    }
+
+    #[test]
+    fn opt_out_and_undocumented_functions() {
+        let expansion = expand(r#""x86-64-v3", "arm64-v2", no_dispatch_doc"#, "/// Sums.");
+        assert_eq!(doc_lines(&outer_fn(&expansion).attrs), [" Sums."]);
+        assert!(!expansion.contains("Runtime-dispatched"));
+
+        // So `missing_docs` still reports them.
+        let expansion = expand(r#""x86-64-v3", "arm64-v2""#, "");
+        assert!(!expansion.contains("Runtime-dispatched"));
+
+        // Nothing is dispatched.
+        let expansion = expand(r#""wasm32-simd128""#, "/// Sums.");
+        assert!(!expansion.contains("Runtime-dispatched"));
+    }
+}
@@ -0,0 +1,139 @@
+//! Callable tier clones for `#[multiversed(expose)]`.
+//!
+//! `func::tiers` is a unit struct in the companion module. Its functions are defined by
+//! inherent impls inside `func`'s body, next to the clones they call, so they forward
+//! to the clones dispatch runs instead of compiling the body again, and see the
+//! function's scope.
+//!
+//! Those impls are what `non_local_definitions` reports, which is allowed: `tiers` can't
+//! be defined in the body, where nothing outside could name it.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::{Ident, ItemFn, parse_quote};
+
+use multiversed_rt::Preset;
+
+use crate::dispatch::{CloneOptions, Forward, Target};
+use crate::private_path;
+
+/// The unit struct `tiers`, for the companion module.
+pub(crate) fn tiers_struct(func: &ItemFn) -> TokenStream {
+    let doc = format!("Each clone of `{}`, to call directly.", func.sig.ident);
+    quote! {
+        #[doc = #doc]
+        #[allow(non_camel_case_types)]
+        pub struct tiers;
+    }
+}
+
+/// `impl func::tiers` with an `unsafe fn` per target, named by its clone suffix, that
+/// calls the clone `{func}__{suffix}`.
+///
+/// Goes next to the clones, in the body of the copy multiversioned for `targets`.
+pub(crate) fn tier_fns(
+    targets: &[Target],
+    forward: &Forward,
+    options: CloneOptions,
+) -> TokenStream {
+    let name = options.name;
+    let fns = targets.iter().map(|target| {
+        let suffix = target.suffix();
+        let mut sig = forward.outer_sig.clone();
+        sig.ident = format_ident!("{suffix}");
+        sig.unsafety = Some(Default::default());
+        let call = forward.call_unsafe(&format_ident!("{}__{suffix}", name.unraw()));
+        let arch = target.arch_cfg();
+        let label = target.label();
+        let doc = format!(
+            "`{name}` compiled for `{label}`.\n\n\
+             # Safety\n\n\
+             The CPU must support every feature of `{label}`; `try_call` checks that."
+        );
+        quote! {
+            #[cfg(#arch)]
+            #[doc = #doc]
+            #[allow(unsafe_code)]
+            pub #sig {
+                // SAFETY: the caller guarantees the clone's features.
+                #call
+            }
+        }
+    });
+    quote! {
+        #[allow(dead_code, non_local_definitions)]
+        impl #name::tiers {
+            #(#fns)*
+        }
+    }
+}
+
+/// `impl func::tiers` with a safe `baseline`, which calls the fallback `baseline`, and
+/// `try_call`, which checks detection before calling a preset's clone.
+///
+/// Goes in the outer function's body, where `baseline` is visible.
+pub(crate) fn tiers_impl(
+    func: &ItemFn,
+    targets: &[Target],
+    baseline: &Ident,
+    options: CloneOptions,
+) -> syn::Result<TokenStream> {
+    let forward = Forward::new(func, None)?;
+    let name = &func.sig.ident;
+    let krate = options.krate;
+    let private = private_path(krate);
+
+    // Raw targets have no `Preset`; only `x86_64_v3()` and the like reach them.
+    let arms = targets.iter().filter_map(|target| {
+        let label = target.label();
+        let preset = Preset::ALL.into_iter().find(|p| p.name() == label)?;
+        let variant = format_ident!("{preset:?}");
+        let arch = target.arch_cfg();
+        let detected = target.detected(krate);
+        let suffix = format_ident!("{}", target.suffix());
+        let call = forward.call_unsafe(&quote! { Self::#suffix });
+        Some(quote! {
+            #[cfg(#arch)]
+            #private::Preset::#variant if #detected => ::core::option::Option::Some(#call),
+        })
+    });
+
+    let mut baseline_sig = forward.outer_sig.clone();
+    baseline_sig.ident = format_ident!("baseline");
+    let call_baseline = forward.call(baseline);
+    let baseline_doc = format!("`{name}` without target features, as the fallback runs it.");
+
+    let mut try_sig = forward.outer_sig.clone();
+    try_sig.ident = format_ident!("try_call");
+    try_sig
+        .inputs
+        .insert(0, parse_quote! { preset: #private::Preset });
+    try_sig.output = match &try_sig.output {
+        syn::ReturnType::Default => parse_quote! { -> ::core::option::Option<()> },
+        syn::ReturnType::Type(_, ty) => parse_quote! { -> ::core::option::Option<#ty> },
+    };
+    let try_doc = format!(
+        "Call the clone of `{name}` for `preset`, or return `None` if `{name}` has no \
+         clone for it on this architecture or the CPU lacks its features."
+    );
+    Ok(quote! {
+        #[allow(dead_code, non_local_definitions)]
+        impl #name::tiers {
+            #[doc = #baseline_doc]
+            pub #baseline_sig {
+                #call_baseline
+            }
+
+            #[doc = #try_doc]
+            #[allow(unused_variables, unsafe_code)]
+            pub #try_sig {
+                // SAFETY: each arm runs its clone only once its features are detected.
+                match preset {
+                    #(#arms)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,782 @@
+#![deny(unsafe_code)]
+
+//! Proc-macro implementation of [multiversed](https://docs.rs/multiversed).
+//!
+//! Depend on `multiversed` instead: the generated code refers to its runtime support,
+//! and it documents the presets, options and cargo features.
+
+#![allow(dead_code)]
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Ident, ItemFn, LitStr, Token, parse_macro_input, parse_quote};
+
+mod autotune;
+mod clone_target;
+mod deterministic;
+mod dispatch;
+mod expose;
+mod report;
+mod target_feature;
+mod tokens;
+
+use autotune::autotune_dispatch;
+use deterministic::{dedup, fused_op_warnings, without_fma};
+use dispatch::{
+    CloneOptions, Forward, Target, baseline_state, companion_module, count_call, dispatch_doc,
+    dispatcher_state, multiversioned_fn, with_items,
+};
+use multiversed_rt::Arch;
+use multiversed_rt::presets::{self, PRESETS, PresetDef};
+
+/// Path the generated code reaches `multiversed` through, unless `crate = "..."` is given.
+fn default_crate_path() -> syn::Path {
+    parse_quote! { ::multiversed }
+}
+
+/// Path of the runtime support module in `multiversed` at `krate`.
+fn private_path(krate: &syn::Path) -> proc_macro2::TokenStream {
+    quote! { #krate::__private }
+}
+
+// ============================================================================
+// Target strings and archmage tokens
+//
+// Both come from the preset table in multiversed-rt (`multiversed_rt::presets`), whose
+// feature lists are generated from archmage token-registry.toml — the source of truth.
+// ============================================================================
+
+// Note: wasm32 has no runtime feature detection, so it gets no clones.
+// The wasm32-simd128 feature exists for documentation but generates no dispatch code.
+// Users must compile with -C target-feature=+simd128 for SIMD on wasm32.
+
+/// Target strings and archmage token names of the presets that get clones.
+///
+/// Highest tier first within each architecture, so `token_bindings` picks the
+/// most capable token a clone satisfies.
+fn archmage_tokens() -> Vec<(String, &'static str)> {
+    PRESETS
+        .iter()
+        .rev()
+        .filter(|def| def.arch != Arch::Wasm32)
+        .map(|def| (def.target_string(), def.token))
+        .collect()
+}
+
+// ============================================================================
+// Preset name resolution
+// ============================================================================
+
+/// Resolve a preset name to its target string, or return the input as-is if it's a raw target.
+fn resolve_target(s: &str) -> Option<String> {
+    match presets::find(s) {
+        // wasm32 - no runtime detection, ignore
+        Some(def) if def.arch == Arch::Wasm32 => None,
+        // Preset name or alias ("arm64" is an alias for "arm64-v2")
+        Some(def) => Some(def.target_string()),
+        // Raw target string - pass through if it looks like a valid target
+        None if s.contains('+') && !s.starts_with("wasm32") => Some(s.to_string()),
+        // Unknown or wasm32 raw target - ignore
+        None => None,
+    }
+}
+
+/// Look up the preset named by a string literal in a macro's input.
+fn find_preset(name: &LitStr) -> syn::Result<&'static PresetDef> {
+    presets::find(&name.value()).ok_or_else(|| {
+        let names: Vec<&str> = PRESETS.iter().map(|def| def.name).collect();
+        syn::Error::new(
+            name.span(),
+            format!(
+                "unknown preset `{}`, expected one of: {}",
+                name.value(),
+                names.join(", ")
+            ),
+        )
+    })
+}
+
+/// The resolved target of the preset named by a string literal in a macro's input.
+fn preset_target(name: &LitStr) -> syn::Result<Target> {
+    let def = find_preset(name)?;
+    Target::parse(&def.target_string())
+}
+
+/// Check if a target string is for x86/x86_64 architecture.
+fn is_x86_target(s: &str) -> bool {
+    s.starts_with("x86_64+") || s.starts_with("x86+") || s.starts_with("x86-64-")
+}
+
+/// Check if a target string is for aarch64 architecture.
+fn is_aarch64_target(s: &str) -> bool {
+    s.starts_with("aarch64+") || s.starts_with("aarch64-")
+}
+
+// ============================================================================
+// Attribute argument parsing
+// ============================================================================
+
+#[derive(Default)]
+struct MultiversedArgs {
+    /// The arguments as written, for the `MULTIVERSED_REPORT` record.
+    source: String,
+    targets: Vec<String>,
+    /// Bind an archmage capability token named `token` in each clone.
+    tokens: bool,
+    /// Leave FMA out of every tier so results match the fallback bit for bit.
+    deterministic_fp: bool,
+    /// Inputs shorter than this run the fallback without dispatching.
+    min_len: Option<usize>,
+    /// The slice argument whose length is compared against `min_len`.
+    len: Option<Ident>,
+    /// Microarchitectures on which the AVX-512 tiers are skipped.
+    avoid_on: Vec<String>,
+    /// Time every tier on first use and keep the fastest.
+    autotune: Option<Autotune>,
+    /// Path the generated code reaches `multiversed` through.
+    krate: Option<syn::Path>,
+    /// Generate the companion module `foo` next to the function. Implied by `expose`.
+    companion: bool,
+    /// Generate `foo::tiers` with each clone as a callable function.
+    expose: Option<Ident>,
+    /// Leave the function's docs without the list of dispatched targets.
+    no_dispatch_doc: bool,
+}
+
+/// What `autotune` measures the tiers on.
+enum Autotune {
+    /// The arguments of the first call.
+    FirstCall,
+    /// A function that calls the given closure with representative arguments.
+    Sample(syn::Path),
+}
+
+impl Parse for MultiversedArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = MultiversedArgs {
+            source: input.cursor().token_stream().to_string(),
+            ..MultiversedArgs::default()
+        };
+
+        while !input.is_empty() {
+            if input.peek(LitStr) {
+                let lit: LitStr = input.parse()?;
+                args.targets.push(lit.value());
+            } else if input.peek(Token![crate]) {
+                let _: Token![crate] = input.parse()?;
+                let _: Token![=] = input.parse()?;
+                let path: LitStr = input.parse()?;
+                args.krate = Some(path.parse()?);
+            } else {
+                let option: Ident = input.parse()?;
+                match option.to_string().as_str() {
+                    "tokens" => args.tokens = true,
+                    "deterministic_fp" => args.deterministic_fp = true,
+                    "companion" => args.companion = true,
+                    "no_dispatch_doc" => args.no_dispatch_doc = true,
+                    "expose" => {
+                        args.companion = true;
+                        args.expose = Some(option);
+                    }
+                    "min_len" => {
+                        let _: Token![=] = input.parse()?;
+                        let value: syn::LitInt = input.parse()?;
+                        args.min_len = Some(value.base10_parse()?);
+                    }
+                    "len" => {
+                        let _: Token![=] = input.parse()?;
+                        args.len = Some(input.parse()?);
+                    }
+                    "avoid_on" => {
+                        let _: Token![=] = input.parse()?;
+                        let content;
+                        syn::bracketed!(content in input);
+                        let names =
+                            content.parse_terminated(<LitStr as Parse>::parse, Token![,])?;
+                        for name in names {
+                            if !AVOID_ON_MICROARCHS.contains(&name.value().as_str()) {
+                                return Err(syn::Error::new(
+                                    name.span(),
+                                    format!(
+                                        "unknown microarchitecture `{}`, expected one of: {}",
+                                        name.value(),
+                                        AVOID_ON_MICROARCHS.join(", ")
+                                    ),
+                                ));
+                            }
+                            args.avoid_on.push(name.value());
+                        }
+                    }
+                    "autotune" => {
+                        args.autotune = Some(if input.peek(Token![=]) {
+                            let _: Token![=] = input.parse()?;
+                            Autotune::Sample(input.parse()?)
+                        } else {
+                            Autotune::FirstCall
+                        });
+                    }
+                    _ => {
+                        return Err(syn::Error::new(
+                            option.span(),
+                            format!("unknown multiversed option `{option}`"),
+                        ));
+                    }
+                }
+            }
+
+            if input.peek(Token![,]) {
+                let _: Token![,] = input.parse()?;
+            }
+        }
+
+        if args.min_len.is_some() != args.len.is_some() {
+            return Err(input.error("`min_len` and `len` must be given together"));
+        }
+        if args.autotune.is_some() && !args.avoid_on.is_empty() {
+            return Err(input.error(
+                "`autotune` and `avoid_on` cannot be combined; autotune measures every tier",
+            ));
+        }
+
+        Ok(args)
+    }
+}
+
+// ============================================================================
+// Default targets from cargo features
+// ============================================================================
+
+// Vec init-then-push pattern required for conditional cfg compilation; the Vec stays
+// empty (and unmutated) when no preset feature is enabled.
+#[allow(clippy::vec_init_then_push, unused_mut)]
+fn default_x86_targets() -> Vec<&'static str> {
+    let mut targets = Vec::new();
+
+    // Higher tiers first (more specific optimizations)
+    #[cfg(any(feature = "x86-64-v4x", feature = "x86-64-v4-modern"))]
+    targets.push("x86-64-v4x");
+
+    #[cfg(feature = "x86-64-v4")]
+    targets.push("x86-64-v4");
+
+    #[cfg(feature = "x86-64-v3")]
+    targets.push("x86-64-v3");
+
+    #[cfg(feature = "x86-64-v2")]
+    targets.push("x86-64-v2");
+
+    targets
+}
+
+#[allow(clippy::vec_init_then_push, unused_mut)]
+fn default_aarch64_targets() -> Vec<&'static str> {
+    let mut targets = Vec::new();
+
+    // Higher tiers first (more specific optimizations)
+    #[cfg(feature = "arm64-v3")]
+    targets.push("arm64-v3");
+
+    // "arm64" is an alias for "arm64-v2" — both activate the arm64-v2 feature,
+    // so we only check arm64-v2 here to avoid duplicate targets.
+    #[cfg(feature = "arm64-v2")]
+    targets.push("arm64-v2");
+
+    targets
+}
+
+// ============================================================================
+// Main attribute macro
+// ============================================================================
+
+/// Multiversion a function with SIMD target presets.
+///
+/// # Usage
+///
+/// ```ignore
+/// use multiversed::multiversed;
+///
+/// // Use cargo feature defaults
+/// #[multiversed]
+/// fn sum(data: &[f32]) -> f32 {
+///     data.iter().sum()
+/// }
+///
+/// // Explicit presets
+/// #[multiversed("x86-64-v4", "arm64-v2")]
+/// fn optimized(data: &[f32]) -> f32 {
+///     data.iter().sum()
+/// }
+///
+/// // Mix presets with raw target strings
+/// #[multiversed("x86-64-v3", "x86_64+avx2+fma+custom")]
+/// fn custom(data: &[f32]) -> f32 {
+///     data.iter().sum()
+/// }
+/// ```
+///
+/// # Arguments
+///
+/// - **No arguments**: Uses targets from enabled cargo features
+/// - **Preset names**: `"x86-64-v3"`, `"arm64"`, etc.
+/// - **Raw target strings**: Any string with `+`, as `arch+feature+...`
+/// - **`tokens`**: Bind an archmage token named `token` in each clone (see crate docs)
+/// - **`deterministic_fp`**: Keep FMA out of every tier (see crate docs)
+/// - **`min_len = N, len = arg`**: Skip dispatch when `arg.len() < N` (see crate docs)
+/// - **`avoid_on = [...]`**: Skip AVX-512 tiers on the listed microarchitectures (see crate docs)
+/// - **`autotune`**: Time each tier on first use and keep the fastest (see crate docs)
+/// - **`crate = "path"`**: Path to `multiversed` in the generated code
+/// - **`companion`**: Generate the `foo::selected_target()` module (see crate docs)
+/// - **`expose`**: Generate `foo::tiers` to call each clone directly (see crate docs)
+/// - **`no_dispatch_doc`**: Don't append the dispatched targets to the docs (see crate docs)
+#[proc_macro_attribute]
+pub fn multiversed(attr: TokenStream, item: TokenStream) -> TokenStream {
+    #[allow(unused_mut)]
+    let mut func = parse_macro_input!(item as ItemFn);
+
+    // force-disable feature: passthrough without any clones
+    #[cfg(feature = "force-disable")]
+    {
+        // Still honor `tokens`, since the body refers to the `token` binding.
+        let args = parse_macro_input!(attr as MultiversedArgs);
+        if let Err(err) = report::record(&func, &args, &[], &[]) {
+            return err.into_compile_error().into();
+        }
+        if args.tokens {
+            func.block.stmts.splice(0..0, tokens::token_bindings());
+        }
+        let krate = args.krate.clone().unwrap_or_else(default_crate_path);
+        func.block
+            .stmts
+            .splice(0..0, clone_target::target_items(None, &krate));
+        let mut items = vec![report::env_dependency()];
+        if args.deterministic_fp {
+            items.extend(fused_op_warnings(&func, &krate));
+        }
+        if let Err(err) = dispatch::insert_items(&mut func, &items) {
+            return err.into_compile_error().into();
+        }
+        // Keep an autotune sample function referenced so it isn't reported as dead code.
+        if let Some(Autotune::Sample(sample)) = &args.autotune {
+            func.block
+                .stmts
+                .insert(0, syn::parse_quote! { let _ = #sample; });
+        }
+        // No clones to expose: `tiers` only has `baseline`, calling the function itself,
+        // and a `try_call` that declines.
+        let tiers = if args.expose.is_some() {
+            let name = func.sig.ident.clone();
+            let options = CloneOptions {
+                krate: &krate,
+                name: &name,
+                tokens: false,
+                avoid_on: &[],
+                register: None,
+                module: None,
+                expose: false,
+            };
+            let tiers_impl = match expose::tiers_impl(&func, &[], &name, options) {
+                Ok(tiers_impl) => tiers_impl,
+                Err(err) => return err.into_compile_error().into(),
+            };
+            if let Err(err) = dispatch::insert_items(&mut func, &[tiers_impl]) {
+                return err.into_compile_error().into();
+            }
+            Some(expose::tiers_struct(&func))
+        } else {
+            None
+        };
+        let companion = args
+            .companion
+            .then(|| dispatch::companion_module(&func, dispatch::baseline_state(), tiers));
+        #[allow(clippy::needless_return)]
+        return quote! { #func #companion }.into();
+    }
+
+    #[cfg(not(feature = "force-disable"))]
+    {
+        let args = parse_macro_input!(attr as MultiversedArgs);
+        multiversed_impl(args, func)
+            .unwrap_or_else(syn::Error::into_compile_error)
+            .into()
+    }
+}
+
+fn multiversed_impl(
+    args: MultiversedArgs,
+    mut func: ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    // Collect targets, separating by architecture
+    let (mut x86_targets, aarch64_targets) = if args.targets.is_empty() {
+        // No explicit targets - use cargo feature defaults
+        let x86: Vec<String> = default_x86_targets()
+            .into_iter()
+            .filter_map(resolve_target)
+            .collect();
+        let aarch64: Vec<String> = default_aarch64_targets()
+            .into_iter()
+            .filter_map(resolve_target)
+            .collect();
+        (x86, aarch64)
+    } else {
+        // Explicit targets - resolve presets and partition by architecture.
+        // Deduplicate because aliases (e.g., "arm64" and "arm64-v2") resolve to the
+        // same target string, and would generate duplicate clones.
+        // Note: wasm32 targets are filtered out (no runtime detection on wasm32)
+        let mut resolved: Vec<String> = Vec::new();
+        for s in &args.targets {
+            if let Some(target) = resolve_target(s)
+                && !resolved.contains(&target)
+            {
+                resolved.push(target);
+            }
+        }
+
+        let x86: Vec<String> = resolved
+            .iter()
+            .filter(|s| is_x86_target(s))
+            .cloned()
+            .collect();
+        let aarch64: Vec<String> = resolved
+            .iter()
+            .filter(|s| is_aarch64_target(s))
+            .cloned()
+            .collect();
+
+        (x86, aarch64)
+    };
+
+    let krate = args.krate.clone().unwrap_or_else(default_crate_path);
+    // Items at the start of the outer function, not the clones, so each is expanded once:
+    // the report's env dependency, `deterministic_fp` warnings and `expose` functions.
+    let mut outer_items = vec![report::env_dependency()];
+    if args.deterministic_fp {
+        let dropped = x86_targets
+            .iter()
+            .filter(|t| without_fma(t).is_none())
+            .map(|t| Target::parse(t).map(|t| t.label()))
+            .collect::<syn::Result<Vec<_>>>()?;
+        if !dropped.is_empty() {
+            if !args.targets.is_empty() {
+                return Err(deterministic::dropped_tiers_error(&dropped));
+            }
+            outer_items.push(deterministic::dropped_tiers_warning(&krate));
+        }
+        x86_targets = x86_targets.iter().filter_map(|t| without_fma(t)).collect();
+        dedup(&mut x86_targets);
+        outer_items.extend(fused_op_warnings(&func, &krate));
+    }
+
+    report::record(&func, &args, &x86_targets, &aarch64_targets)?;
+    let multiversioned = !x86_targets.is_empty() || !aarch64_targets.is_empty();
+
+    let name = func.sig.ident.clone();
+    let options = CloneOptions {
+        krate: &krate,
+        name: &name,
+        tokens: args.tokens,
+        avoid_on: &args.avoid_on,
+        register: Some(&name),
+        module: args.companion.then_some(&name),
+        expose: args.expose.is_some(),
+    };
+    let targets = x86_targets
+        .iter()
+        .chain(&aarch64_targets)
+        .map(|target| Target::parse(target))
+        .collect::<syn::Result<Vec<_>>>()?;
+    // Undocumented functions stay undocumented, so `missing_docs` still reports them.
+    let documented = func.attrs.iter().any(|attr| attr.path().is_ident("doc"));
+    if multiversioned && documented && !args.no_dispatch_doc {
+        func.attrs.extend(dispatch_doc(&targets));
+    }
+    let tiers = args.expose.as_ref().map(|_| expose::tiers_struct(&func));
+
+    // The dispatcher state goes in the companion module if there is one; otherwise the
+    // code generating the dispatcher keeps it inside the function.
+    if !multiversioned || (args.min_len.is_none() && args.autotune.is_none()) {
+        let companion = options.module.map(|_| {
+            let state = if multiversioned {
+                dispatcher_state(&targets, options)
+            } else {
+                baseline_state()
+            };
+            companion_module(&func, state, tiers.clone())
+        });
+        if options.expose {
+            // Without targets the function is its own fallback.
+            let baseline = if multiversioned {
+                format_ident!("{}__baseline", name.unraw())
+            } else {
+                name.clone()
+            };
+            outer_items.push(expose::tiers_impl(&func, &targets, &baseline, options)?);
+        }
+        let func = multiversioned_fn(func, &x86_targets, &aarch64_targets, options)?;
+        let func = with_items(func, &outer_items)?;
+        return Ok(quote! { #func #companion });
+    }
+
+    // Dispatch policy: an outer function with the original signature picks between
+    // nested copies of the body, each multiversioned (or not) on its own.
+    let forward = Forward::new(&func, args.len.as_ref())?;
+    let mut items = Vec::new();
+    let mut branches = Vec::new();
+
+    let fallback = Forward::copy(&func, &format!("{}__baseline", name.unraw()));
+    let fallback_ident = fallback.sig.ident.clone();
+    items.push(multiversioned_fn(fallback, &[], &[], options)?);
+    if options.expose {
+        items.push(expose::tiers_impl(
+            &func,
+            &targets,
+            &fallback_ident,
+            options,
+        )?);
+    }
+
+    if let (Some(min_len), Some(len)) = (args.min_len, &args.len) {
+        let count = count_call(quote! { 0 }, &targets, options);
+        let call = forward.call(&fallback_ident);
+        branches.push((quote! { #len.len() < #min_len }, quote! { #count #call }));
+    }
+
+    let (dispatch, state) = if let Some(autotune) = &args.autotune {
+        let (autotune_items, dispatch, state) = autotune_dispatch(
+            &func,
+            &forward,
+            &fallback_ident,
+            autotune,
+            &x86_targets,
+            &aarch64_targets,
+            options,
+        )?;
+        items.extend(autotune_items);
+        (dispatch, state)
+    } else {
+        let tiered = Forward::copy(&func, "__multiversed_tiered");
+        let call = forward.call(&tiered.sig.ident);
+        items.push(multiversioned_fn(
+            tiered,
+            &x86_targets,
+            &aarch64_targets,
+            options,
+        )?);
+        let state = options.module.map(|_| dispatcher_state(&targets, options));
+        (call, state)
+    };
+
+    let companion = state.map(|state| companion_module(&func, state, tiers));
+    let func = with_items(forward.wrap(&func, items, branches, dispatch), &outer_items)?;
+    Ok(quote! { #func #companion })
+}
+
+// ============================================================================
+// In-body helpers
+// ============================================================================
+
+/// The [`Target`](https://docs.rs/target-features) the running copy of a
+/// `#[multiversed]` function's body is compiled for: the clone's features, or the
+/// build's baseline in the fallback.
+///
+/// ```ignore
+/// #[multiversed("x86-64-v3", "arm64-v2")]
+/// fn has_avx2() -> bool {
+///     multiversed::target::selected_target!().supports_feature_str("avx2")
+/// }
+/// ```
+///
+/// A constant, so branches on it are resolved at compile time. Only valid in the body
+/// of a `#[multiversed]` function.
+#[proc_macro]
+pub fn selected_target(input: TokenStream) -> TokenStream {
+    parse_macro_input!(input as syn::parse::Nothing);
+    quote! { __MULTIVERSED_TARGET }.into()
+}
+
+/// Pick an expression by the target the running copy of a `#[multiversed]` function's
+/// body is compiled for.
+///
+/// ```ignore
+/// multiversed::target::match_target! {
+///     "x86_64+avx2+fma" => sum_avx2(data),
+///     "aarch64+neon" | "x86_64+sse4.1" => sum_128(data),
+///     _ => data.iter().sum(),
+/// }
+/// ```
+///
+/// The first arm whose architecture and features the copy is compiled with is kept,
+/// and the others are removed like `cfg`d code. Features enabled for the whole build
+/// count as well. Only valid in the body of a `#[multiversed]` function.
+#[proc_macro]
+pub fn match_target(input: TokenStream) -> TokenStream {
+    let input = proc_macro2::TokenStream::from(input);
+    quote! { __multiversed_match_target! { #input } }.into()
+}
+
+/// `match_target!` with the target string of the copy it is expanded in. Generated by
+/// `#[multiversed]`; not public API.
+#[doc(hidden)]
+#[proc_macro]
+pub fn __match_target(input: TokenStream) -> TokenStream {
+    parse_macro_input!(input as clone_target::MatchTarget)
+        .expand()
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// ============================================================================
+// Preset strings
+// ============================================================================
+
+/// Expand a preset name to its full target string, as a string literal.
+///
+/// `targets!("x86-64-v3")` expands to `"x86_64+sse+sse2+...+movbe"`.
+#[proc_macro]
+pub fn targets(input: TokenStream) -> TokenStream {
+    preset_literal(input, PresetDef::target_string)
+}
+
+/// Expand a preset name to its features in `target_feature` format, as a string literal.
+///
+/// `features!("x86-64-v3")` expands to `"sse,sse2,...,movbe"`.
+#[proc_macro]
+pub fn features(input: TokenStream) -> TokenStream {
+    preset_literal(input, |def| def.features().join(","))
+}
+
+fn preset_literal(input: TokenStream, expand: impl Fn(&PresetDef) -> String) -> TokenStream {
+    let name = parse_macro_input!(input as LitStr);
+    match find_preset(&name) {
+        Ok(def) => {
+            let literal = LitStr::new(&expand(def), name.span());
+            quote! { #literal }.into()
+        }
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+// ============================================================================
+// Runtime detection
+// ============================================================================
+
+/// Input of `detect!`: a preset name and an optional `crate = "path"`.
+struct DetectArgs {
+    preset: LitStr,
+    krate: Option<syn::Path>,
+}
+
+impl Parse for DetectArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let preset = input.parse()?;
+        let mut krate = None;
+        if input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let _: Token![crate] = input.parse()?;
+            let _: Token![=] = input.parse()?;
+            let path: LitStr = input.parse()?;
+            krate = Some(path.parse()?);
+            let _: Option<Token![,]> = input.parse()?;
+        }
+        Ok(DetectArgs { preset, krate })
+    }
+}
+
+/// Check at runtime whether the host supports every feature of a preset.
+///
+/// `detect!("x86-64-v4x")` expands to a `bool` expression that looks up each feature in
+/// the shared capability snapshot (`__private::has_feature`), as the generated
+/// dispatchers do, skipping the lookups when the features are enabled at compile time.
+/// `redetect()` drops the snapshot, so later checks see a fresh one. It is `false` on
+/// other architectures.
+#[proc_macro]
+pub fn detect(input: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(input as DetectArgs);
+    let target = match preset_target(&args.preset) {
+        Ok(target) => target,
+        Err(err) => return err.into_compile_error().into(),
+    };
+    let krate = args.krate.unwrap_or_else(default_crate_path);
+    let arch = target.arch_cfg();
+    let detected = target.detected(&krate);
+    quote! {{
+        #[cfg(#arch)]
+        let detected = #detected;
+        #[cfg(not(#arch))]
+        let detected = false;
+        detected
+    }}
+    .into()
+}
+
+// ============================================================================
+// Compile-time preset checks
+// ============================================================================
+
+/// Compile an item only if the build's baseline already enables every feature of a preset.
+///
+/// `#[cfg_preset("x86-64-v3")]` expands to
+/// `#[cfg(all(target_arch = "x86_64", target_feature = "sse", ...))]`, which holds for
+/// builds with `-C target-cpu=x86-64-v3` or a matching `-C target-feature` list.
+#[proc_macro_attribute]
+pub fn cfg_preset(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let name = parse_macro_input!(attr as LitStr);
+    let item = proc_macro2::TokenStream::from(item);
+    match enabled_cfg(&name) {
+        Ok(cfg) => quote! { #[cfg(#cfg)] #item }.into(),
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+/// Whether the build's baseline already enables every feature of a preset, as a `bool`.
+///
+/// `cfg_preset_enabled!("x86-64-v3")` is the `cfg!` counterpart of
+/// `#[cfg_preset("x86-64-v3")]`.
+#[proc_macro]
+pub fn cfg_preset_enabled(input: TokenStream) -> TokenStream {
+    let name = parse_macro_input!(input as LitStr);
+    match enabled_cfg(&name) {
+        Ok(cfg) => quote! { ::core::cfg!(#cfg) }.into(),
+        Err(err) => err.into_compile_error().into(),
+    }
+}
+
+/// The `cfg` predicate that holds when the preset named by `name` is compiled in.
+fn enabled_cfg(name: &LitStr) -> syn::Result<proc_macro2::TokenStream> {
+    Ok(preset_target(name)?.enabled_cfg())
+}
+
+// ============================================================================
+// Hand-written kernels
+// ============================================================================
+
+/// Enable a preset's features on a hand-written kernel.
+///
+/// Expands each preset into `#[target_feature(enable = "...")]` under a `cfg_attr` for
+/// its architecture, with at most one preset per architecture. Compiling the function
+/// for an architecture none of the presets covers is an error.
+///
+/// ```ignore
+/// #[cfg(target_arch = "x86_64")]
+/// #[multiversed::target_feature("x86-64-v3")]
+/// unsafe fn add_avx2(a: __m256, b: __m256) -> __m256 {
+///     _mm256_add_ps(a, b)
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn target_feature(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let names = parse_macro_input!(attr with Punctuated::<LitStr, Token![,]>::parse_terminated);
+    let func = parse_macro_input!(item as ItemFn);
+    target_feature::target_feature_impl(names, func)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+// ============================================================================
+// Microarchitecture policy (avoid_on)
+// ============================================================================
+
+/// Microarchitectures accepted by `avoid_on`, identified at runtime by
+/// `__private::on_microarch` in the multiversed crate.
+const AVOID_ON_MICROARCHS: &[&str] = &["skylake-x", "icelake", "zen4", "zen5"];
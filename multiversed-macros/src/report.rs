@@ -0,0 +1,171 @@
+//! The build-time report written when `MULTIVERSED_REPORT` is set.
+
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::path::Path;
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::ItemFn;
+
+use crate::MultiversedArgs;
+
+/// The environment variable naming the file expansions are appended to.
+const REPORT_VAR: &str = "MULTIVERSED_REPORT";
+
+/// Append one JSON line describing this expansion to the file named by
+/// `MULTIVERSED_REPORT`, if it is set.
+///
+/// `x86_64` and `aarch64` are the target strings the function is cloned for, empty
+/// under `force-disable`. The line is written with a single `write` on a file opened
+/// for appending, so parallel rustc processes don't interleave their records.
+pub(crate) fn record(
+    func: &ItemFn,
+    args: &MultiversedArgs,
+    x86_64: &[String],
+    aarch64: &[String],
+) -> syn::Result<()> {
+    let Some(path) = std::env::var_os(REPORT_VAR).filter(|path| !path.is_empty()) else {
+        return Ok(());
+    };
+
+    let ident = &func.sig.ident;
+    let span = ident.span().unwrap();
+    let krate = std::env::var("CARGO_CRATE_NAME").ok();
+    // rustc runs in the workspace root, which relative source paths start from.
+    let module = span
+        .local_file()
+        .zip(std::env::var_os("CARGO_MANIFEST_DIR"))
+        .and_then(|(file, package)| {
+            let file = std::env::current_dir().ok()?.join(file);
+            let file = file.strip_prefix(package).ok()?;
+            Some(module_path(krate.as_deref().unwrap_or("crate"), file))
+        });
+
+    let mut line = String::from("{");
+    let _ = write!(
+        line,
+        "\"crate\":{},\"module\":{},\"file\":{},\"line\":{},\"column\":{},\"function\":{},\
+         \"args\":{},\"defaults\":{},\"x86_64\":{},\"aarch64\":{}",
+        krate
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        module
+            .as_deref()
+            .map_or_else(|| "null".to_string(), json_string),
+        json_string(&span.file()),
+        span.line(),
+        span.column(),
+        json_string(&ident.to_string()),
+        json_string(&args.source),
+        args.targets.is_empty(),
+        json_list(x86_64),
+        json_list(aarch64),
+    );
+    line.push_str("}\n");
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(line.as_bytes()))
+        .map_err(|err| {
+            syn::Error::new(
+                ident.span(),
+                format!(
+                    "cannot append to the {REPORT_VAR} file `{}`: {err}",
+                    path.to_string_lossy()
+                ),
+            )
+        })
+}
+
+/// `const _ = option_env!("MULTIVERSED_REPORT")`, for the function's body.
+///
+/// rustc records the variables `option_env!` reads, so cargo recompiles the crate when
+/// the variable changes. It doesn't notice [`record`] reading it.
+pub(crate) fn env_dependency() -> TokenStream {
+    quote! {
+        const _: ::core::option::Option<&str> = ::core::option_env!(#REPORT_VAR);
+    }
+}
+
+/// The module path of items in `file`, relative to the package, by cargo's layout:
+/// `krate::a::b` for `src/a/b.rs` or `src/a/b/mod.rs`, and `krate` for a target's root
+/// file such as `src/lib.rs` or `tests/foo.rs`.
+///
+/// Inline `mod` blocks and `#[path]` attributes aren't visible to a proc macro, so
+/// they are left out.
+fn module_path(krate: &str, file: &Path) -> String {
+    let parts: Vec<String> = file
+        .with_extension("")
+        .iter()
+        .map(|part| part.to_string_lossy().into_owned())
+        .collect();
+    let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+    // The target's directory: `src`, `src/bin/<name>`, `tests/<name>`, ...
+    let target = match parts.as_slice() {
+        ["src", "bin", ..] => 3,
+        ["src", ..] => 1,
+        ["tests" | "benches" | "examples", ..] => 2,
+        _ => 0,
+    };
+    let mut modules = &parts[target.min(parts.len())..];
+    if let [parent @ .., "lib" | "main" | "mod"] = modules {
+        modules = parent;
+    }
+    std::iter::once(krate)
+        .chain(modules.iter().copied())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+fn json_list(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|item| json_string(item)).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_path_follows_cargo_layout() {
+        for (file, module) in [
+            ("src/lib.rs", "app"),
+            ("src/main.rs", "app"),
+            ("src/kernels.rs", "app::kernels"),
+            ("src/kernels/mod.rs", "app::kernels"),
+            ("src/kernels/resize.rs", "app::kernels::resize"),
+            ("src/bin/tool.rs", "app"),
+            ("src/bin/tool/main.rs", "app"),
+            ("src/bin/tool/args.rs", "app::args"),
+            ("tests/app.rs", "app"),
+            ("tests/app/main.rs", "app"),
+            ("benches/app/kernels.rs", "app::kernels"),
+        ] {
+            assert_eq!(module_path("app", Path::new(file)), module, "{file}");
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! `#[multiversed::target_feature("preset", ...)]` for hand-written kernels.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{Attribute, ItemFn, LitStr, Token, parse_quote};
+
+use crate::find_preset;
+
+/// Expand the presets in `names` into one `#[target_feature]` per architecture on `func`.
+pub(crate) fn target_feature_impl(
+    names: Punctuated<LitStr, Token![,]>,
+    mut func: ItemFn,
+) -> syn::Result<TokenStream> {
+    if names.is_empty() {
+        return Err(syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "expected a preset name, e.g. `#[multiversed::target_feature(\"x86-64-v3\")]`",
+        ));
+    }
+
+    let mut arches: Vec<&str> = Vec::new();
+    let mut attrs: Vec<Attribute> = Vec::new();
+    for name in &names {
+        let def = find_preset(name)?;
+        let arch = def.arch.target_arch();
+        if let Some(i) = arches.iter().position(|a| *a == arch) {
+            return Err(syn::Error::new(
+                name.span(),
+                format!(
+                    "`{}` and `{}` both target {arch}; give one preset per architecture",
+                    names[i].value(),
+                    name.value()
+                ),
+            ));
+        }
+        arches.push(arch);
+
+        let features = def.features().join(",");
+        attrs.push(parse_quote! {
+            #[cfg_attr(target_arch = #arch, target_feature(enable = #features))]
+        });
+    }
+    func.attrs.splice(0..0, attrs);
+
+    // `cfg`s on the function are resolved before attribute macros run, so whether it
+    // is compiled for a listed architecture can only be checked in the output.
+    let presets: Vec<String> = names
+        .iter()
+        .map(|name| format!("`{}`", name.value()))
+        .collect();
+    let message = format!(
+        "`{}` is compiled for an architecture not covered by {}; add a preset for it \
+         or gate the function with `#[cfg(target_arch = ...)]`",
+        func.sig.ident,
+        presets.join(", ")
+    );
+    Ok(quote! {
+        #[cfg(not(any(#(target_arch = #arches),*)))]
+        ::core::compile_error!(#message);
+
+        #func
+    })
+}
@@ -0,0 +1,102 @@
+//! archmage token bindings for `#[multiversed(tokens)]`.
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{Ident, Stmt, parse_quote};
+
+use crate::archmage_tokens;
+use crate::dispatch::Target;
+
+/// Build the `token` binding inserted at the top of a body multiversioned for `arches`.
+///
+/// On those architectures `match_target!` resolves per clone at compile time, so each
+/// clone binds the token for the highest preset its features satisfy. Elsewhere the
+/// body binds the fallback's token, as [`token_bindings`] does.
+pub(crate) fn matched_token_bindings(arches: &[&str], krate: &syn::Path) -> Vec<Stmt> {
+    let mut bindings: Vec<Stmt> = Vec::new();
+    if !arches.is_empty() {
+        let arms = archmage_tokens().into_iter().map(|(preset, token)| {
+            let forge = forge(token);
+            quote! { #preset => #forge, }
+        });
+        let forge_scalar = forge("ScalarToken");
+        bindings.push(parse_quote! {
+            #[cfg(any(#(target_arch = #arches),*))]
+            #[allow(unused_variables, unused_unsafe, unsafe_code)]
+            // SAFETY: match_target! only keeps an arm whose features this clone is compiled
+            // with, and the dispatcher only calls the clone after detecting those features.
+            let token = unsafe {
+                #krate::target::match_target! {
+                    #(#arms)*
+                    _ => #forge_scalar,
+                }
+            };
+        });
+    }
+    for mut binding in token_bindings() {
+        if let Stmt::Local(local) = &mut binding {
+            local.attrs.insert(
+                0,
+                parse_quote! { #[cfg(not(any(#(target_arch = #arches),*)))] },
+            );
+        }
+        bindings.push(binding);
+    }
+    bindings
+}
+
+/// Build the `token` binding inserted at the top of a body that isn't multiversioned.
+///
+/// It binds the token for the highest preset enabled at compile time, `Wasm128Token` on
+/// wasm32 with simd128, and `ScalarToken` otherwise.
+pub(crate) fn token_bindings() -> Vec<Stmt> {
+    let presets: Vec<(Target, &str)> = archmage_tokens()
+        .iter()
+        .map(|(preset, token)| {
+            let preset = Target::parse(preset).expect("preset target strings are well-formed");
+            (preset, *token)
+        })
+        .collect();
+
+    let mut bindings: Vec<Stmt> = Vec::new();
+    let mut higher: Vec<TokenStream> = Vec::new();
+    for (preset, token) in &presets {
+        let enabled = preset.enabled_cfg();
+        let forge = forge(token);
+        bindings.push(parse_quote! {
+            #[cfg(all(#enabled, not(any(#(#higher),*))))]
+            #[allow(unused_variables, unused_unsafe, unsafe_code)]
+            // SAFETY: the program is compiled with every feature of the token.
+            let token = unsafe { #forge };
+        });
+        higher.push(enabled);
+    }
+
+    let forge_wasm = forge("Wasm128Token");
+    bindings.push(parse_quote! {
+        #[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+        #[allow(unused_variables, unused_unsafe, unsafe_code)]
+        // SAFETY: simd128 is enabled at compile time.
+        let token = unsafe { #forge_wasm };
+    });
+
+    let forge_scalar = forge("ScalarToken");
+    bindings.push(parse_quote! {
+        #[cfg(not(any(
+            #(#higher,)*
+            all(target_arch = "wasm32", target_feature = "simd128")
+        )))]
+        #[allow(unused_variables, unused_unsafe, unsafe_code)]
+        // SAFETY: ScalarToken proves no CPU features.
+        let token = unsafe { #forge_scalar };
+    });
+
+    bindings
+}
+
+/// Construct `token` without a runtime check, through archmage's `from_context()`
+/// (0.9.29 and later), which is `unsafe` outside a matching `#[target_feature]` context.
+fn forge(token: &str) -> TokenStream {
+    let token = Ident::new(token, Span::call_site());
+    quote! { ::archmage::#token::from_context() }
+}
@@ -0,0 +1,378 @@
+//! The host's target features, detected once per process.
+//!
+//! Every feature a preset uses is detected in one pass on first use and kept as a
+//! bitset. [`Preset`] and the generated dispatchers read that snapshot instead of each
+//! running their own detection. [`redetect`](crate::redetect()) drops it, so the next
+//! read probes the host again.
+//!
+//! The first snapshot comes from std's detection. std caches it for the life of the
+//! process, so on x86_64 the redetection probe reads CPUID itself.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Arch, Preset};
+
+/// Set in [`SNAPSHOT`] once detection ran; the bits below are [`FEATURES`].
+const TAKEN: u64 = 1 << 63;
+
+/// Set in [`SNAPSHOT`] by [`reset`], so the next detection uses [`probe`].
+const STALE: u64 = 1 << 62;
+
+static SNAPSHOT: AtomicU64 = AtomicU64::new(0);
+
+/// The target features of the host that presets use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    detected: u64,
+}
+
+impl Capabilities {
+    /// Whether the host has `feature`, in rustc's `target_feature` name.
+    ///
+    /// Only features used by a preset of the host's architecture are detected; others
+    /// are reported missing.
+    pub fn has(self, feature: &str) -> bool {
+        FEATURES
+            .iter()
+            .position(|f| *f == feature)
+            .is_some_and(|i| self.detected & (1 << i) != 0)
+    }
+
+    /// Whether the host has every feature of `preset`.
+    pub fn supports(self, preset: Preset) -> bool {
+        Arch::host() == Some(preset.arch()) && preset.features().iter().all(|f| self.has(f))
+    }
+
+    /// The detected features.
+    pub fn features(self) -> impl Iterator<Item = &'static str> {
+        FEATURES
+            .iter()
+            .enumerate()
+            .filter(move |(i, _)| self.detected & (1 << i) != 0)
+            .map(|(_, feature)| *feature)
+    }
+}
+
+/// The host's features, detected on the first call and shared by the whole process.
+///
+/// ```
+/// let host = multiversed_rt::capabilities();
+/// println!("{}", host.features().collect::<Vec<_>>().join(","));
+/// ```
+pub fn capabilities() -> Capabilities {
+    let mut snapshot = SNAPSHOT.load(Ordering::Relaxed);
+    if snapshot & TAKEN == 0 {
+        // Racing threads detect the same features; either store is fine.
+        let detected = if snapshot & STALE == 0 {
+            detect()
+        } else {
+            probe()
+        };
+        snapshot = detected | TAKEN;
+        SNAPSHOT.store(snapshot, Ordering::Relaxed);
+    }
+    Capabilities {
+        detected: snapshot & !TAKEN,
+    }
+}
+
+/// Drop the snapshot; the next [`capabilities`] call probes the host again.
+pub(crate) fn reset() {
+    SNAPSHOT.store(STALE, Ordering::Relaxed);
+}
+
+/// Whether the host's features differ from the snapshot, probing it again.
+pub(crate) fn changed() -> bool {
+    let snapshot = SNAPSHOT.load(Ordering::Relaxed);
+    snapshot & TAKEN != 0 && snapshot & !TAKEN != probe()
+}
+
+/// Feature check of the generated dispatchers, for features that presets use.
+#[doc(hidden)]
+#[cold]
+pub fn has_feature(feature: &str) -> bool {
+    capabilities().has(feature)
+}
+
+// ============================================================================
+// Detection
+// ============================================================================
+
+/// `(feature, register, bit)`: where CPUID reports each feature. Registers are leaf 1
+/// `ecx`/`edx`, leaf 7 `ebx`/`ecx` and leaf `0x8000_0001` `ecx`.
+#[cfg(target_arch = "x86_64")]
+const CPUID_BITS: &[(&str, Reg, u32)] = &[
+    ("sse", Reg::Edx1, 25),
+    ("sse2", Reg::Edx1, 26),
+    ("sse3", Reg::Ecx1, 0),
+    ("ssse3", Reg::Ecx1, 9),
+    ("sse4.1", Reg::Ecx1, 19),
+    ("sse4.2", Reg::Ecx1, 20),
+    ("popcnt", Reg::Ecx1, 23),
+    ("cmpxchg16b", Reg::Ecx1, 13),
+    ("pclmulqdq", Reg::Ecx1, 1),
+    ("aes", Reg::Ecx1, 25),
+    ("avx", Reg::Ecx1, 28),
+    ("avx2", Reg::Ebx7, 5),
+    ("fma", Reg::Ecx1, 12),
+    ("bmi1", Reg::Ebx7, 3),
+    ("bmi2", Reg::Ebx7, 8),
+    ("f16c", Reg::Ecx1, 29),
+    ("lzcnt", Reg::EcxExt, 5),
+    ("movbe", Reg::Ecx1, 22),
+    ("avx512f", Reg::Ebx7, 16),
+    ("avx512bw", Reg::Ebx7, 30),
+    ("avx512cd", Reg::Ebx7, 28),
+    ("avx512dq", Reg::Ebx7, 17),
+    ("avx512vl", Reg::Ebx7, 31),
+    ("avx512vpopcntdq", Reg::Ecx7, 14),
+    ("avx512ifma", Reg::Ebx7, 21),
+    ("avx512vbmi", Reg::Ecx7, 1),
+    ("avx512vbmi2", Reg::Ecx7, 6),
+    ("avx512bitalg", Reg::Ecx7, 12),
+    ("avx512vnni", Reg::Ecx7, 11),
+    ("vpclmulqdq", Reg::Ecx7, 10),
+    ("gfni", Reg::Ecx7, 8),
+    ("vaes", Reg::Ecx7, 9),
+];
+
+#[cfg(target_arch = "x86_64")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Reg {
+    Ecx1,
+    Edx1,
+    Ebx7,
+    Ecx7,
+    EcxExt,
+}
+
+#[cfg(target_arch = "x86_64")]
+const FEATURES: &[&str] = &{
+    let mut names = [""; CPUID_BITS.len()];
+    let mut i = 0;
+    while i < names.len() {
+        names[i] = CPUID_BITS[i].0;
+        i += 1;
+    }
+    names
+};
+
+#[cfg(target_arch = "x86_64")]
+fn detect() -> u64 {
+    use std::arch::is_x86_feature_detected as detected;
+
+    FEATURES.iter().enumerate().fold(0, |bits, (i, feature)| {
+        // The macro needs literal names.
+        let detected = match *feature {
+            "sse" => detected!("sse"),
+            "sse2" => detected!("sse2"),
+            "sse3" => detected!("sse3"),
+            "ssse3" => detected!("ssse3"),
+            "sse4.1" => detected!("sse4.1"),
+            "sse4.2" => detected!("sse4.2"),
+            "popcnt" => detected!("popcnt"),
+            "cmpxchg16b" => detected!("cmpxchg16b"),
+            "pclmulqdq" => detected!("pclmulqdq"),
+            "aes" => detected!("aes"),
+            "avx" => detected!("avx"),
+            "avx2" => detected!("avx2"),
+            "fma" => detected!("fma"),
+            "bmi1" => detected!("bmi1"),
+            "bmi2" => detected!("bmi2"),
+            "f16c" => detected!("f16c"),
+            "lzcnt" => detected!("lzcnt"),
+            "movbe" => detected!("movbe"),
+            "avx512f" => detected!("avx512f"),
+            "avx512bw" => detected!("avx512bw"),
+            "avx512cd" => detected!("avx512cd"),
+            "avx512dq" => detected!("avx512dq"),
+            "avx512vl" => detected!("avx512vl"),
+            "avx512vpopcntdq" => detected!("avx512vpopcntdq"),
+            "avx512ifma" => detected!("avx512ifma"),
+            "avx512vbmi" => detected!("avx512vbmi"),
+            "avx512vbmi2" => detected!("avx512vbmi2"),
+            "avx512bitalg" => detected!("avx512bitalg"),
+            "avx512vnni" => detected!("avx512vnni"),
+            "vpclmulqdq" => detected!("vpclmulqdq"),
+            "gfni" => detected!("gfni"),
+            "vaes" => detected!("vaes"),
+            _ => unreachable!("{feature} has a CPUID bit but no std detection"),
+        };
+        bits | (u64::from(detected) << i)
+    })
+}
+
+/// Read the features from CPUID directly: std caches `is_x86_feature_detected!` for
+/// the life of the process, which would keep [`redetect`](crate::redetect()) from
+/// seeing a new host. Follows std's rules, including that the AVX and AVX-512
+/// features also need the OS to save their registers.
+#[cfg(target_arch = "x86_64")]
+fn probe() -> u64 {
+    use core::arch::x86_64::{__cpuid, __cpuid_count};
+
+    let max_leaf = __cpuid(0).eax;
+    if max_leaf < 1 {
+        return 0;
+    }
+    let leaf1 = __cpuid(1);
+    let leaf7 = if max_leaf >= 7 {
+        let leaf7 = __cpuid_count(7, 0);
+        [leaf7.ebx, leaf7.ecx]
+    } else {
+        [0, 0]
+    };
+    let ecx_ext = if __cpuid(0x8000_0000).eax >= 0x8000_0001 {
+        __cpuid(0x8000_0001).ecx
+    } else {
+        0
+    };
+
+    // XCR0 needs xsave (bit 26) enabled by the OS (osxsave, bit 27).
+    let xcr0 = if leaf1.ecx & (0b11 << 26) == 0b11 << 26 {
+        xcr0()
+    } else {
+        0
+    };
+    let os_avx = xcr0 & 0b110 == 0b110;
+    // macOS allocates a thread's AVX-512 state on its first AVX-512 instruction, and
+    // XCR0 leaves the state out until then, though the OS supports it.
+    let os_avx512 = os_avx && (cfg!(target_os = "macos") || xcr0 & 0b1110_0000 == 0b1110_0000);
+
+    CPUID_BITS
+        .iter()
+        .enumerate()
+        .filter(|(_, (name, reg, bit))| {
+            let value = match reg {
+                Reg::Ecx1 => leaf1.ecx,
+                Reg::Edx1 => leaf1.edx,
+                Reg::Ebx7 => leaf7[0],
+                Reg::Ecx7 => leaf7[1],
+                Reg::EcxExt => ecx_ext,
+            };
+            let os_saves = if name.starts_with("avx512") {
+                os_avx512
+            } else if matches!(
+                *name,
+                "avx" | "avx2" | "fma" | "f16c" | "vaes" | "vpclmulqdq"
+            ) {
+                os_avx
+            } else {
+                true
+            };
+            value & (1 << bit) != 0 && os_saves
+        })
+        .fold(0, |bits, (i, _)| bits | (1 << i))
+}
+
+/// The OS-enabled register state, from `xgetbv`.
+#[cfg(target_arch = "x86_64")]
+#[allow(unsafe_code)]
+fn xcr0() -> u64 {
+    #[target_feature(enable = "xsave")]
+    unsafe fn xgetbv() -> u64 {
+        // SAFETY: xsave is enabled, as the caller checked.
+        unsafe { core::arch::x86_64::_xgetbv(0) }
+    }
+    // SAFETY: only called once CPUID reports xsave enabled by the OS.
+    unsafe { xgetbv() }
+}
+
+/// The aarch64 features, which the OS reports once per process, so std's cached
+/// detection is current.
+#[cfg(target_arch = "aarch64")]
+const FEATURES: &[&str] = &[
+    "neon", "crc", "rdm", "dotprod", "fp16", "aes", "sha2", "fhm", "fcma", "sha3", "i8mm", "bf16",
+];
+
+#[cfg(target_arch = "aarch64")]
+fn detect() -> u64 {
+    use std::arch::is_aarch64_feature_detected as detected;
+
+    // The macro needs literal names, hence the list in `FEATURES` order.
+    [
+        detected!("neon"),
+        detected!("crc"),
+        detected!("rdm"),
+        detected!("dotprod"),
+        detected!("fp16"),
+        detected!("aes"),
+        detected!("sha2"),
+        detected!("fhm"),
+        detected!("fcma"),
+        detected!("sha3"),
+        detected!("i8mm"),
+        detected!("bf16"),
+    ]
+    .into_iter()
+    .enumerate()
+    .fold(0, |bits, (i, detected)| bits | (u64::from(detected) << i))
+}
+
+/// Only x86_64 needs its own probe; std's detection elsewhere is as current as it gets.
+#[cfg(not(target_arch = "x86_64"))]
+use detect as probe;
+
+/// wasm32 has no runtime detection; report what the program was compiled with.
+#[cfg(target_arch = "wasm32")]
+const FEATURES: &[&str] = &["simd128"];
+
+#[cfg(target_arch = "wasm32")]
+fn detect() -> u64 {
+    u64::from(cfg!(target_feature = "simd128"))
+}
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "wasm32"
+)))]
+const FEATURES: &[&str] = &[];
+
+#[cfg(not(any(
+    target_arch = "x86_64",
+    target_arch = "aarch64",
+    target_arch = "wasm32"
+)))]
+fn detect() -> u64 {
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_covers_host_presets() {
+        const { assert!(FEATURES.len() < 62) };
+        for preset in Preset::ALL
+            .into_iter()
+            .filter(|p| Some(p.arch()) == Arch::host())
+        {
+            for feature in preset.features() {
+                assert!(
+                    FEATURES.contains(feature),
+                    "{preset}: {feature} not detected"
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(target_arch = "x86_64")]
+    fn cpuid_matches_std() {
+        let (cpuid, std) = (probe(), detect());
+        for (i, feature) in FEATURES.iter().enumerate() {
+            assert_eq!(cpuid & (1 << i), std & (1 << i), "{feature}");
+        }
+    }
+
+    #[test]
+    fn snapshot_is_stable() {
+        let host = capabilities();
+        assert_eq!(host, capabilities());
+        for feature in host.features() {
+            assert!(host.has(feature));
+        }
+        assert!(!host.has("not-a-feature"));
+    }
+}
@@ -0,0 +1,296 @@
+#![deny(unsafe_code)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+//! Runtime companion to [`multiversed`].
+//!
+//! This crate provides the attribute's preset vocabulary at runtime, so applications
+//! can log and assert the capability level of the host using the names the attribute
+//! accepts. `multiversed` re-exports it; depend on it directly to use presets without
+//! the macro. [`registry()`] lists the multiversioned functions in the program and the
+//! preset each dispatches to, and [`init_all()`] resolves them all at startup from one
+//! [`capabilities()`] snapshot of the host.
+//!
+//! ```
+//! use multiversed_rt::{Arch, Preset};
+//!
+//! let preset: Preset = "x86-64-v4-modern".parse().unwrap();
+//! assert_eq!(preset, Preset::X86_64V4x);
+//! assert_eq!(preset.to_string(), "x86-64-v4x");
+//! assert!(preset.implies(Preset::X86_64V3));
+//!
+//! if let Some(best) = Preset::best_for_host(Arch::X86_64) {
+//!     println!("running at {best} ({})", best.archmage_token());
+//! }
+//! ```
+//!
+//! [`multiversed`]: https://docs.rs/multiversed
+
+use core::fmt;
+use core::str::FromStr;
+use std::sync::LazyLock;
+
+mod capabilities;
+#[doc(hidden)]
+pub mod presets;
+mod registry;
+
+#[doc(hidden)]
+pub use capabilities::has_feature;
+pub use capabilities::{Capabilities, capabilities};
+use presets::{PRESETS, PresetDef};
+pub use registry::{
+    Function, init_all, redetect, redetect_checkpoint, registry, set_redetect_hook,
+};
+#[cfg(feature = "stats")]
+pub use registry::{FunctionStats, stats_snapshot};
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+#[doc(hidden)]
+pub use {linkme, registry::FUNCTIONS};
+
+// ============================================================================
+// Architectures
+// ============================================================================
+
+/// An architecture with `multiversed` presets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+    Wasm32,
+}
+
+impl Arch {
+    /// The architecture this program was compiled for, if it has presets.
+    pub const fn host() -> Option<Arch> {
+        if cfg!(target_arch = "x86_64") {
+            Some(Arch::X86_64)
+        } else if cfg!(target_arch = "aarch64") {
+            Some(Arch::Aarch64)
+        } else if cfg!(target_arch = "wasm32") {
+            Some(Arch::Wasm32)
+        } else {
+            None
+        }
+    }
+
+    /// The architecture's `target_arch` name.
+    pub const fn target_arch(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::Aarch64 => "aarch64",
+            Arch::Wasm32 => "wasm32",
+        }
+    }
+}
+
+// ============================================================================
+// Presets
+// ============================================================================
+
+/// A `multiversed` target preset.
+///
+/// Within an architecture, variants are ordered from least to most capable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Preset {
+    /// `x86-64-v2`: SSE4.2 + POPCNT (Nehalem 2008+, Bulldozer 2011+)
+    X86_64V2,
+    /// `x86-64-v3`: AVX2 + FMA + BMI1/2 (Haswell 2013+, Zen 1 2017+)
+    X86_64V3,
+    /// `x86-64-v4`: AVX-512 F/BW/CD/DQ/VL (Skylake-X 2017+, Zen 4 2022+)
+    X86_64V4,
+    /// `x86-64-v4x` (alias `x86-64-v4-modern`): v4 + VNNI, VBMI2, GFNI, VAES, ... (Ice Lake 2019+, Zen 4 2022+)
+    X86_64V4x,
+    /// `arm64-v2` (alias `arm64`): NEON, CRC, DotProd, FP16, AES (Cortex-A55+, Apple M1+, Graviton 2+)
+    Arm64V2,
+    /// `arm64-v3`: v2 + SHA3, I8MM, BF16 (Cortex-A510+, Apple M2+, Graviton 3+)
+    Arm64V3,
+    /// `wasm32-simd128`: 128-bit WebAssembly SIMD
+    Wasm128,
+}
+
+impl Preset {
+    /// Every preset, least capable first within each architecture.
+    pub const ALL: [Preset; 7] = [
+        Preset::X86_64V2,
+        Preset::X86_64V3,
+        Preset::X86_64V4,
+        Preset::X86_64V4x,
+        Preset::Arm64V2,
+        Preset::Arm64V3,
+        Preset::Wasm128,
+    ];
+
+    /// The preset's row in the preset table.
+    const fn def(self) -> &'static PresetDef {
+        &PRESETS[self as usize]
+    }
+
+    /// The canonical name, as accepted by `#[multiversed(...)]`.
+    pub const fn name(self) -> &'static str {
+        self.def().name
+    }
+
+    /// The architecture the preset targets.
+    pub const fn arch(self) -> Arch {
+        self.def().arch
+    }
+
+    /// The matching archmage token type.
+    pub const fn archmage_token(self) -> &'static str {
+        self.def().token
+    }
+
+    /// Every target feature the preset enables, in rustc's names.
+    pub fn features(self) -> &'static [&'static str] {
+        static FEATURES: LazyLock<Vec<Vec<&'static str>>> =
+            LazyLock::new(|| PRESETS.iter().map(PresetDef::features).collect());
+        &FEATURES[self as usize]
+    }
+
+    /// Features of the preset the host lacks. Empty when [`is_supported`](Self::is_supported).
+    ///
+    /// Everything is missing on a different architecture. wasm32 has no runtime
+    /// detection, so `simd128` counts as present only if the program was compiled with it.
+    pub fn missing_features(self) -> Vec<&'static str> {
+        if Arch::host() != Some(self.arch()) {
+            return self.features().to_vec();
+        }
+        self.features()
+            .iter()
+            .copied()
+            .filter(|feature| !capabilities().has(feature))
+            .collect()
+    }
+
+    /// Whether the host supports every feature of the preset.
+    pub fn is_supported(self) -> bool {
+        capabilities().supports(self)
+    }
+
+    /// Whether every feature of `other` is also a feature of `self`.
+    ///
+    /// Presets imply themselves; presets of different architectures never imply each other.
+    pub fn implies(self, other: Preset) -> bool {
+        self.arch() == other.arch()
+            && other
+                .features()
+                .iter()
+                .all(|feature| self.features().contains(feature))
+    }
+
+    /// The most capable preset for `arch` that the host supports.
+    ///
+    /// `None` if the host is a different architecture or supports none of its presets.
+    pub fn best_for_host(arch: Arch) -> Option<Preset> {
+        Preset::ALL
+            .into_iter()
+            .rev()
+            .find(|preset| preset.arch() == arch && preset.is_supported())
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = ParsePresetError;
+
+    /// Parse a preset name, including the aliases `x86-64-v4-modern` and `arm64`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        presets::find(s)
+            .and_then(|def| Preset::ALL.into_iter().find(|p| p.name() == def.name))
+            .ok_or_else(|| ParsePresetError {
+                name: s.to_string(),
+            })
+    }
+}
+
+/// Error returned when parsing an unknown preset name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePresetError {
+    name: String,
+}
+
+impl fmt::Display for ParsePresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown multiversed preset `{}`", self.name)
+    }
+}
+
+impl std::error::Error for ParsePresetError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_round_trip() {
+        for preset in Preset::ALL {
+            assert_eq!(preset.name().parse::<Preset>(), Ok(preset));
+            assert_eq!(preset.to_string(), preset.name());
+        }
+        assert_eq!("x86-64-v4-modern".parse(), Ok(Preset::X86_64V4x));
+        assert_eq!("arm64".parse(), Ok(Preset::Arm64V2));
+        let err = "x86-64-v5".parse::<Preset>().unwrap_err();
+        assert_eq!(err.to_string(), "unknown multiversed preset `x86-64-v5`");
+    }
+
+    #[test]
+    fn table_matches_variants() {
+        assert_eq!(PRESETS.len(), Preset::ALL.len());
+        for (preset, def) in Preset::ALL.into_iter().zip(PRESETS) {
+            assert_eq!(preset.name(), def.name);
+            for alias in def.aliases {
+                assert_eq!(alias.parse(), Ok(preset));
+            }
+            if let Some(parent) = def.parent() {
+                let parent: Preset = parent.name.parse().unwrap();
+                assert!(parent < preset && parent.arch() == preset.arch());
+                assert!(preset.implies(parent));
+            }
+            let features = preset.features();
+            assert!(
+                features
+                    .iter()
+                    .enumerate()
+                    .all(|(i, f)| !features[..i].contains(f))
+            );
+        }
+    }
+
+    #[test]
+    fn implies_follows_tiers() {
+        use Preset::*;
+        assert!(X86_64V4x.implies(X86_64V4));
+        assert!(X86_64V4.implies(X86_64V3));
+        assert!(X86_64V3.implies(X86_64V2));
+        assert!(X86_64V3.implies(X86_64V3));
+        assert!(!X86_64V3.implies(X86_64V4));
+        assert!(Arm64V3.implies(Arm64V2));
+        assert!(!Arm64V2.implies(Arm64V3));
+        assert!(!X86_64V4x.implies(Arm64V2));
+        assert!(!Wasm128.implies(X86_64V2));
+    }
+
+    #[test]
+    fn host_detection_is_consistent() {
+        for preset in Preset::ALL {
+            assert_eq!(preset.is_supported(), preset.missing_features().is_empty());
+            if preset.is_supported() {
+                for lower in Preset::ALL.into_iter().filter(|p| preset.implies(*p)) {
+                    assert!(lower.is_supported(), "{preset} without {lower}");
+                }
+            }
+        }
+        for arch in [Arch::X86_64, Arch::Aarch64, Arch::Wasm32] {
+            if let Some(best) = Preset::best_for_host(arch) {
+                assert_eq!(best.arch(), arch);
+                assert_eq!(Arch::host(), Some(arch));
+                assert!(best.is_supported());
+            }
+        }
+    }
+}
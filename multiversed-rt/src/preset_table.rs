@@ -0,0 +1,123 @@
+// @generated by tools/gen-presets from tools/gen-presets/token-registry.toml.
+// Do not edit; update the registry snapshot and run `cargo run -p gen-presets`.
+
+/// Every preset, least capable first within each architecture.
+///
+/// The order matches the variants of [`Preset`](crate::Preset).
+pub const PRESETS: &[PresetDef] = &[
+    // x86-64-v2: SSE4.2 + POPCNT + CX16 (Nehalem 2008+, Bulldozer 2011+)
+    PresetDef {
+        name: "x86-64-v2",
+        aliases: &[],
+        arch: Arch::X86_64,
+        parent: None,
+        adds: &[
+            "sse",
+            "sse2",
+            "sse3",
+            "ssse3",
+            "sse4.1",
+            "sse4.2",
+            "popcnt",
+            "cmpxchg16b",
+        ],
+        token: "X64V2Token",
+    },
+    // x86-64-v3: AVX2 + FMA (Haswell 2013+, Zen 1 2017+)
+    PresetDef {
+        name: "x86-64-v3",
+        aliases: &[],
+        arch: Arch::X86_64,
+        parent: Some("x86-64-v2"),
+        adds: &[
+            "avx",
+            "avx2",
+            "fma",
+            "bmi1",
+            "bmi2",
+            "f16c",
+            "lzcnt",
+            "movbe",
+        ],
+        token: "X64V3Token",
+    },
+    // x86-64-v4: AVX-512 (Skylake-X 2017+, Zen 4 2022+) — psABI v4 (F+CD+VL+DQ+BW) plus AES and PCLMULQDQ
+    PresetDef {
+        name: "x86-64-v4",
+        aliases: &[],
+        arch: Arch::X86_64,
+        parent: Some("x86-64-v3"),
+        adds: &[
+            "pclmulqdq",
+            "aes",
+            "avx512f",
+            "avx512bw",
+            "avx512cd",
+            "avx512dq",
+            "avx512vl",
+        ],
+        token: "X64V4Token",
+    },
+    // x86-64-v4x: Full modern AVX-512 (Ice Lake 2019+, Zen 4 2022+) — adds VNNI, VBMI2, BITALG, GFNI, VAES, VPCLMULQDQ
+    PresetDef {
+        name: "x86-64-v4x",
+        aliases: &["x86-64-v4-modern"],
+        arch: Arch::X86_64,
+        parent: Some("x86-64-v4"),
+        adds: &[
+            "avx512vpopcntdq",
+            "avx512ifma",
+            "avx512vbmi",
+            "avx512vbmi2",
+            "avx512bitalg",
+            "avx512vnni",
+            "vpclmulqdq",
+            "gfni",
+            "vaes",
+        ],
+        token: "X64V4xToken",
+    },
+    // arm64-v2: Modern ARM baseline (Cortex-A55+, Apple M1+, Graviton 2+). "arm64" is an alias for backwards compatibility.
+    PresetDef {
+        name: "arm64-v2",
+        aliases: &["arm64"],
+        arch: Arch::Aarch64,
+        parent: None,
+        adds: &[
+            "neon",
+            "crc",
+            "rdm",
+            "dotprod",
+            "fp16",
+            "aes",
+            "sha2",
+        ],
+        token: "Arm64V2Token",
+    },
+    // arm64-v3: Full modern ARM SIMD (Cortex-A510+, Apple M2+, Graviton 3+)
+    PresetDef {
+        name: "arm64-v3",
+        aliases: &[],
+        arch: Arch::Aarch64,
+        parent: Some("arm64-v2"),
+        adds: &[
+            "fhm",
+            "fcma",
+            "sha3",
+            "i8mm",
+            "bf16",
+        ],
+        token: "Arm64V3Token",
+    },
+    // wasm32-simd128: no runtime detection, so the attribute generates no clones for it
+    PresetDef {
+        name: "wasm32-simd128",
+        aliases: &[],
+        arch: Arch::Wasm32,
+        parent: None,
+        adds: &[
+            "simd128",
+        ],
+        token: "Wasm128Token",
+    },
+];
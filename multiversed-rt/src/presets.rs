@@ -0,0 +1,61 @@
+//! The preset table: the one place preset names, aliases and feature lists are defined.
+//!
+//! [`Preset`](crate::Preset) reads it at runtime, and `multiversed-macros` builds the
+//! attribute's target strings and archmage token bindings from it at compile time.
+//! Feature lists are generated from a snapshot of archmage's token-registry.toml — the
+//! source of truth — by `tools/gen-presets`, whose test fails if they drift apart.
+//!
+//! Not public API: the table layout may change in any release.
+
+use crate::Arch;
+
+/// One preset: its names, the preset it extends, and the features it adds.
+#[derive(Debug)]
+pub struct PresetDef {
+    /// Canonical name, as accepted by `#[multiversed(...)]` and printed by `Preset`.
+    pub name: &'static str,
+    /// Other names accepted for the preset.
+    pub aliases: &'static [&'static str],
+    pub arch: Arch,
+    /// The next lower preset of the same architecture, whose features this one includes.
+    pub parent: Option<&'static str>,
+    /// Features enabled on top of the parent's, in rustc's names.
+    pub adds: &'static [&'static str],
+    /// The matching archmage token type.
+    pub token: &'static str,
+}
+
+// `PRESETS`, generated by tools/gen-presets from the vendored token-registry.toml.
+include!("preset_table.rs");
+
+/// Look up a preset by canonical name or alias.
+pub fn find(name: &str) -> Option<&'static PresetDef> {
+    PRESETS
+        .iter()
+        .find(|def| def.name == name || def.aliases.contains(&name))
+}
+
+impl PresetDef {
+    /// The preset this one extends.
+    pub fn parent(&self) -> Option<&'static PresetDef> {
+        self.parent
+            .map(|name| find(name).expect("preset parents name presets in the table"))
+    }
+
+    /// Every feature the preset enables: the parent's, then its own additions.
+    pub fn features(&self) -> Vec<&'static str> {
+        let mut features = self.parent().map_or_else(Vec::new, PresetDef::features);
+        features.extend_from_slice(self.adds);
+        features
+    }
+
+    /// The attribute's target string, `arch+feature+...`.
+    pub fn target_string(&self) -> String {
+        let mut target = self.arch.target_arch().to_string();
+        for feature in self.features() {
+            target.push('+');
+            target.push_str(feature);
+        }
+        target
+    }
+}
@@ -0,0 +1,223 @@
+//! Process-wide registry of `#[multiversed]` functions.
+//!
+//! Every multiversioned function places an entry in a link-time collected slice, so the
+//! registry lists them all without any initialization code, including functions that
+//! have not been called yet. Entries point at the dispatcher's own cache of the chosen
+//! clone, so [`Function::selected`] reads exactly what the next call will use.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+#[doc(hidden)]
+#[linkme::distributed_slice]
+pub static FUNCTIONS: [Function];
+
+/// Every `#[multiversed]` function with clones for the architecture this program was
+/// compiled for.
+///
+/// Functions in crates that the program doesn't link are missing, as are functions
+/// without a target for this architecture, which have nothing to dispatch.
+///
+/// ```
+/// for function in multiversed_rt::registry() {
+///     let selected = function.selected().unwrap_or("not called yet");
+///     println!("{}: {selected} (tiers: {})", function.path(), function.targets().join(", "));
+/// }
+/// ```
+pub fn registry() -> &'static [Function] {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+    return &FUNCTIONS;
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    return &[];
+}
+
+/// Resolve every registered dispatcher now rather than on its first call.
+///
+/// Call it at startup, so the first call of each kernel doesn't pay for feature
+/// detection in the middle of a request. All dispatchers read the same
+/// [`capabilities`](crate::capabilities()) snapshot, so the host is only probed once.
+/// Autotuned functions measure real inputs and still tune on their first call.
+///
+/// ```
+/// multiversed_rt::init_all();
+/// for function in multiversed_rt::registry() {
+///     println!("{}: {}", function.path(), function.selected().unwrap_or("autotuned later"));
+/// }
+/// ```
+pub fn init_all() {
+    crate::capabilities();
+    for function in registry() {
+        if let Some(resolve) = function.resolve {
+            resolve();
+        }
+    }
+}
+
+/// A `#[multiversed]` function in the [`registry`].
+#[derive(Debug)]
+pub struct Function {
+    path: &'static str,
+    targets: &'static [&'static str],
+    /// `usize::MAX` until the dispatcher resolves, then 0 for the fallback and `i` for
+    /// `targets[i - 1]`.
+    selected: &'static AtomicUsize,
+    /// Calls of the fallback, then of each target; empty without the `stats` feature.
+    #[cfg_attr(not(feature = "stats"), allow(dead_code))]
+    calls: &'static [AtomicU64],
+    /// Resolves `selected`, unless the function is autotuned.
+    resolve: Option<fn() -> usize>,
+    /// `selected` before the dispatcher resolves: 1 if the best target is enabled at
+    /// compile time, as nothing is detected then.
+    unresolved: usize,
+}
+
+impl Function {
+    #[doc(hidden)]
+    pub const fn new(
+        path: &'static str,
+        targets: &'static [&'static str],
+        selected: &'static AtomicUsize,
+        calls: &'static [AtomicU64],
+        resolve: Option<fn() -> usize>,
+        unresolved: usize,
+    ) -> Self {
+        Function {
+            path,
+            targets,
+            selected,
+            calls,
+            resolve,
+            unresolved,
+        }
+    }
+
+    /// The function's path, e.g. `my_crate::kernels::sum`.
+    pub fn path(&self) -> &'static str {
+        self.path
+    }
+
+    /// The function's targets for this architecture, highest priority first.
+    ///
+    /// Targets given as presets are listed by their canonical names, raw target strings
+    /// as written.
+    pub fn targets(&self) -> &'static [&'static str] {
+        self.targets
+    }
+
+    /// The target the function dispatches to, or `"baseline"` for the fallback.
+    ///
+    /// `None` until the first call resolves it, unless the best target is enabled at
+    /// compile time.
+    pub fn selected(&self) -> Option<&'static str> {
+        match self.selected.load(Ordering::Relaxed) {
+            usize::MAX => None,
+            0 => Some("baseline"),
+            i => self.targets.get(i - 1).copied(),
+        }
+    }
+}
+
+// ============================================================================
+// Redetection
+// ============================================================================
+
+/// Check of [`redetect_checkpoint`] set by [`set_redetect_hook`].
+static HOOK: Mutex<Option<fn() -> bool>> = Mutex::new(None);
+
+/// Forget the host's features and every dispatcher's resolved tier, so each resolves
+/// again on its next call.
+///
+/// For processes whose CPU can change under them, like VMs that are live-migrated to
+/// a different host model. Call it where no multiversioned call is in flight, such as
+/// between requests: a call that already read its tier still runs that clone.
+/// Autotuned functions tune again. Functions whose best target is enabled at compile
+/// time keep it, since the whole program needs it anyway.
+///
+/// On x86_64 the features are read from CPUID again. aarch64 hosts report features to
+/// a process once, at startup, so the tiers resolve as before.
+pub fn redetect() {
+    crate::capabilities::reset();
+    for function in registry() {
+        function
+            .selected
+            .store(function.unresolved, Ordering::Relaxed);
+    }
+}
+
+/// Set the check [`redetect_checkpoint`] runs to tell whether the host changed, such as
+/// a hypervisor's migration notice, or `None` for the default probe.
+pub fn set_redetect_hook(changed: Option<fn() -> bool>) {
+    *HOOK.lock().unwrap_or_else(PoisonError::into_inner) = changed;
+}
+
+/// [`redetect`] if the host changed, returning whether it did.
+///
+/// Call it at a boundary where no multiversioned call is in flight. Without a hook it
+/// probes the host's features again and compares them with the snapshot the
+/// dispatchers resolved from. That takes a few CPUID instructions, each of which may
+/// exit to the hypervisor, so keep checkpoints out of hot loops.
+///
+/// ```
+/// // Between two requests:
+/// if multiversed_rt::redetect_checkpoint() {
+///     eprintln!("CPU changed; dispatchers will resolve again");
+/// }
+/// ```
+pub fn redetect_checkpoint() -> bool {
+    let hook = *HOOK.lock().unwrap_or_else(PoisonError::into_inner);
+    let changed = match hook {
+        Some(changed) => changed(),
+        None => crate::capabilities::changed(),
+    };
+    if changed {
+        redetect();
+    }
+    changed
+}
+
+// ============================================================================
+// Call counters (stats)
+// ============================================================================
+
+/// Calls of one function per tier, from [`stats_snapshot`].
+#[cfg(feature = "stats")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionStats {
+    /// The function's path, as in [`Function::path`].
+    pub path: &'static str,
+    /// `("baseline", calls)` for the fallback, then each of [`Function::targets`] with
+    /// its calls.
+    pub calls: Vec<(&'static str, u64)>,
+}
+
+/// The number of calls each registered function made to each of its tiers so far.
+///
+/// Counted by the dispatchers of crates compiled with `multiversed`'s `stats` feature;
+/// functions from crates compiled without it are left out. With `min_len`, calls below
+/// the threshold count as `baseline`.
+///
+/// ```
+/// for stats in multiversed_rt::stats_snapshot() {
+///     for (tier, calls) in &stats.calls {
+///         println!("{} {tier}: {calls}", stats.path);
+///     }
+/// }
+/// ```
+#[cfg(feature = "stats")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+pub fn stats_snapshot() -> Vec<FunctionStats> {
+    registry()
+        .iter()
+        .filter(|function| !function.calls.is_empty())
+        .map(|function| FunctionStats {
+            path: function.path,
+            calls: core::iter::once("baseline")
+                .chain(function.targets.iter().copied())
+                .zip(function.calls)
+                .map(|(tier, calls)| (tier, calls.load(Ordering::Relaxed)))
+                .collect(),
+        })
+        .collect()
+}
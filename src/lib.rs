@@ -1,9 +1,14 @@
 #![deny(unsafe_code)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
 
-//! Attribute macros wrapping `multiversion` with predefined SIMD target presets.
+//! Function multiversioning with predefined SIMD target presets.
 //!
-//! This crate provides the `#[multiversed]` attribute that wraps `#[multiversion::multiversion]`
-//! with carefully curated target sets for each architecture.
+//! The `#[multiversed]` attribute compiles a function once per target preset, with the
+//! preset's features enabled, and calls the best copy the host CPU supports. Target sets
+//! are carefully curated for each architecture. Each copy is the function's body with
+//! the preset's features enabled by `#[target_feature]`. The generated code reaches its
+//! runtime support through `::multiversed::__private`, so `multiversed` is the single
+//! dependency needed. The [`Preset`] enum names the same presets at runtime.
 //!
 //! # Usage
 //!
@@ -29,6 +34,34 @@
 //! }
 //! ```
 //!
+//! # In-Body Helpers
+//!
+//! [`target::selected_target!`] and [`target::match_target!`] tell, inside the body of a
+//! `#[multiversed]` function, which copy is running. They are resolved at compile time
+//! per clone:
+//!
+//! ```ignore
+//! use multiversed::target::{match_target, selected_target};
+//!
+//! #[multiversed("x86-64-v3", "arm64-v2")]
+//! fn lanes() -> usize {
+//!     match_target! {
+//!         "x86_64+avx2" => 8,
+//!         "aarch64+neon" => 4,
+//!         _ => 1,
+//!     }
+//! }
+//!
+//! #[multiversed("x86-64-v3")]
+//! fn enabled_features() -> usize {
+//!     selected_target!().features().count()
+//! }
+//! ```
+//!
+//! In the fallback, and on architectures without targets, they see the build's baseline
+//! features. They replace multiversion's macros of the same names, which don't work in
+//! `#[multiversed]` functions.
+//!
 //! # Cargo Features (Presets)
 //!
 //! Feature lists match the [archmage token registry] — the source of truth.
@@ -44,7 +77,7 @@
 //! |---------|----------------|--------------|----------|
 //! | `x86-64-v2` | X64V2Token | SSE4.2, POPCNT | Nehalem 2008+, Bulldozer 2011+ |
 //! | `x86-64-v3` | X64V3Token | AVX2, FMA, BMI1/2 | Haswell 2013+, Zen 1 2017+ |
-//! | `x86-64-v4` | X64V4Token | AVX-512 (F/BW/DQ/VL/CD) | Skylake-X 2017+, Zen 4 2022+ |
+//! | `x86-64-v4` | X64V4Token | AVX-512 (F/BW/DQ/VL/CD), AES, PCLMULQDQ | Skylake-X 2017+, Zen 4 2022+ |
 //! | `x86-64-v4-modern` / `x86-64-v4x` | X64V4xToken | + VNNI, VBMI2, GFNI, VAES | Ice Lake 2019+, Zen 4 2022+ |
 //!
 //! **Note**: Intel consumer CPUs (Alder Lake 12th gen through Arrow Lake) do NOT have
@@ -62,281 +95,405 @@
 //!
 //! | Feature | Archmage Token | Notes |
 //! |---------|----------------|-------|
-//! | `wasm32-simd128` | Wasm128Token | No-op (multiversion elides on wasm32) |
+//! | `wasm32-simd128` | Wasm128Token | No-op (wasm32 has no runtime detection) |
 //!
 //! # Attribute Arguments
 //!
 //! The `#[multiversed]` attribute accepts:
 //! - **No arguments**: Uses targets from enabled cargo features
 //! - **Preset names**: `"x86-64-v3"`, `"arm64"`, etc.
-//! - **Raw target strings**: Any string containing `+` is passed through as-is
+//! - **Raw target strings**: `arch+feature+...`, e.g. `"x86_64+avx2+fma"`; CPU names
+//!   (`"x86_64/skylake"`) are not accepted
+//! - **`tokens`**: Binds an [archmage] capability token named `token` in every clone
+//! - **`deterministic_fp`**: Keeps FMA out of every tier (see below)
+//! - **`min_len = N, len = arg`**: Runs the fallback without dispatching when the slice
+//!   argument `arg` is shorter than `N` (see below)
+//! - **`avoid_on = ["zen4", ...]`**: Skips the AVX-512 tiers on the listed
+//!   microarchitectures (see below)
+//! - **`autotune`** / **`autotune = sample_fn`**: Times each tier once and keeps the
+//!   fastest (see below)
+//! - **`crate = "path"`**: Path to `multiversed` in the generated code (see below)
+//! - **`companion`**: Generates the `foo::selected_target()` module (see below)
+//! - **`expose`**: Generates `foo::tiers`, to call each clone directly (see below)
+//! - **`no_dispatch_doc`**: Leaves the function's docs as written (see below)
 //!
 //! Multiple arguments are comma-separated and all are included in the target list.
+//!
+//! # archmage Tokens
+//!
+//! With `#[multiversed(tokens)]`, each clone starts with a `token` binding whose type is
+//! the archmage token matching that clone's features (`X64V3Token` in the x86-64-v3 clone,
+//! `Arm64V2Token` in the arm64-v2 clone, and so on). The fallback clone binds
+//! `ScalarToken`. The token is forged without a runtime check, since the dispatcher has
+//! already verified the features, so the body can call archmage's safe SIMD APIs directly.
+//! The calling crate must depend on `archmage` 0.9.29 or later.
+//!
+//! ```ignore
+//! #[multiversed(tokens, "x86-64-v3", "arm64-v2")]
+//! pub fn scale(data: &mut [f32], factor: f32) {
+//!     scale_with(token, data, factor) // generic over archmage::SimdToken
+//! }
+//! ```
+//!
+//! [archmage]: https://github.com/imazen/archmage
+//!
+//! # Deterministic Floating Point
+//!
+//! rustc never fuses `a * b + c` on its own, and `mul_add` is correctly rounded with or
+//! without hardware FMA, so plain arithmetic already gives the same results in every
+//! clone. What differs is code that picks a fused path by the clone's features:
+//! `match_target!`, archmage tokens, FMA intrinsics. `#[multiversed(deterministic_fp)]`
+//! removes `fma` from every x86 tier so such code takes the unfused path everywhere.
+//! Reduced presets keep their names (`x86-64-v3`). aarch64 tiers are kept as they are.
+//!
+//! AVX-512 implies FMA, so `x86-64-v4`, `x86-64-v4x` and raw targets with any `avx512*`
+//! feature can't be kept: listing one is an error, and dropping one enabled by cargo
+//! features warns. The macro also warns on each call to `mul_add` or an FMA intrinsic
+//! (`_mm*_fmadd_*`, `vfmaq_*`, ...) in the body:
+//!
+//! ```compile_fail
+//! #![deny(deprecated)]
+//! # use multiversed::multiversed;
+//! #[multiversed(deterministic_fp, "x86-64-v3")]
+//! fn axpy(a: f32, x: f32, y: f32) -> f32 {
+//!     a.mul_add(x, y) // error: use of deprecated constant `...::FUSED_MULTIPLY_ADD`
+//! }
+//! ```
+//!
+//! ```
+//! #![deny(deprecated)]
+//! # use multiversed::multiversed;
+//! #[multiversed(deterministic_fp, "x86-64-v3")]
+//! fn axpy(a: f32, x: f32, y: f32) -> f32 {
+//!     a * x + y
+//! }
+//! # assert_eq!(axpy(2.0, 3.0, 1.0), 7.0);
+//! ```
+//!
+//! # Input-Size Threshold
+//!
+//! For tiny inputs, dispatch and wide-vector warm-up can cost more than they save.
+//! `#[multiversed(min_len = 64, len = data)]` calls a plain fallback copy of the function
+//! when `data.len() < 64`, and the tiered dispatcher otherwise. `len` must name an
+//! argument with a `len()` method. Methods taking `self` are not supported. The
+//! `size_sweep` group in `benches/dispatch_overhead.rs` shows where the crossover lands.
+//!
+//! # Microarchitecture Policy
+//!
+//! Some CPUs have AVX-512 but run it poorly for certain kernels: Zen 4 double-pumps
+//! 512-bit operations (and `vpcompressw` is slow there), and Skylake-X downclocks on
+//! heavy 512-bit code. `#[multiversed(avoid_on = ["zen4", "skylake-x"])]` reads the CPU
+//! vendor, family and model once at runtime and, on the listed microarchitectures,
+//! dispatches among the tiers without AVX-512 (normally `x86-64-v3`) even though the
+//! AVX-512 features are present. Accepted names: `skylake-x` (including Cascade Lake
+//! and Cooper Lake), `icelake`, `zen4`, `zen5`. Methods taking `self` are not supported.
+//!
+//! # Autotuning
+//!
+//! `#[multiversed(autotune)]` times the fallback and every tier the host can run on the
+//! first call, then calls the fastest from then on. Measuring reruns the function with
+//! the first call's arguments, so they must be shared references or primitive values;
+//! `&mut`, owned and interior-mutable arguments are rejected.
+//! `#[multiversed(autotune = sample)]` measures on representative inputs instead:
+//! `sample` is called once with a `&mut dyn FnMut(args...)` to invoke on them.
+//!
+//! If `MULTIVERSED_AUTOTUNE_CACHE` names a file, the choice is stored there keyed by
+//! the CPU brand string and the function path, and later processes on the same CPU
+//! model skip measuring. The cache is only used on x86. `autotune` cannot be combined
+//! with `avoid_on` and does not support async functions, methods taking `self`, or
+//! generic functions, since every instantiation would share the tuned tier.
+//!
+//! # Dispatch Information
+//!
+//! With `#[multiversed(companion)]`, the attribute generates a module `foo` next to
+//! `fn foo`, with the same visibility. `foo::selected_target()` returns the target
+//! `foo` dispatches to on this CPU, detecting it if `foo` hasn't been called yet, and
+//! `foo::available_targets()` lists the targets compiled for this architecture:
+//!
+//! ```
+//! # use multiversed::multiversed;
+//! #[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2", companion)]
+//! fn sum(data: &[f32]) -> f32 {
+//!     data.iter().sum()
+//! }
+//!
+//! // e.g. "sum: x86-64-v3 of x86-64-v4x, x86-64-v3"
+//! println!("sum: {} of {}", sum::selected_target(), sum::available_targets().join(", "));
+//! ```
+//!
+//! Presets are reported by their canonical names, raw target strings as written, and
+//! the fallback as `"baseline"`. With `min_len`, short inputs run the fallback whatever
+//! the selected target; with `autotune`, it's the best supported target until the first
+//! call has been tuned. The module is opt-in because it takes the function's name in
+//! the type namespace, and a module can't be generated inside an `impl` block, so
+//! associated functions can't have one.
+//!
+//! # Calling Tiers Directly
+//!
+//! For benchmarks and differential tests, `#[multiversed(expose)]` adds `foo::tiers`
+//! with one function per target, named like the clones without the `foo__` prefix:
+//! `unsafe fn x86_64_v3(..)`, `unsafe fn arm64_v2(..)`, and so on, each compiled for
+//! its target, plus a safe `baseline(..)`. `try_call(preset, ..)` runs the preset's
+//! clone if the CPU supports it, returning `None` otherwise:
+//!
+//! ```
+//! use multiversed::{Preset, multiversed};
+//!
+//! #[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2", expose)]
+//! fn sum(data: &[f32]) -> f32 {
+//!     data.iter().sum()
+//! }
+//!
+//! let data = [1.0, 2.0, 3.0];
+//! let expected = sum::tiers::baseline(&data);
+//! for preset in [Preset::X86_64V4x, Preset::X86_64V3, Preset::Arm64V2] {
+//!     if let Some(result) = sum::tiers::try_call(preset, &data) {
+//!         assert_eq!(result, expected, "{preset}");
+//!     }
+//! }
+//! ```
+//!
+//! `tiers` is a unit struct, and these are its associated functions, forwarding to the
+//! clones dispatch calls, so the body isn't compiled again. Raw targets get a function
+//! but no `Preset` for `try_call`. `expose` implies `companion`.
+//!
+//! # Generated Docs
+//!
+//! The attribute appends a line to a documented function's docs listing the tiers it
+//! dispatches to, grouped by architecture, so rustdoc shows them:
+//!
+//! > *Runtime-dispatched: x86-64-v4x, x86-64-v3 (x86_64); arm64-v2 (aarch64)*
+//!
+//! Undocumented functions are left alone, so `missing_docs` still reports them. Under
+//! `force-disable` nothing is added; `#[multiversed(no_dispatch_doc)]` opts out.
+//!
+//! # Registry
+//!
+//! Every multiversioned function is listed by [`registry()`], collected at link time,
+//! with its targets for the running architecture and the one it dispatches to:
+//!
+//! ```
+//! for function in multiversed::registry() {
+//!     let selected = function.selected().unwrap_or("not called yet");
+//!     println!("{}: {selected}", function.path());
+//! }
+//! ```
+//!
+//! A function resolves its target on the first call, so log the registry after the
+//! kernels of interest have run, or after [`init_all()`], which resolves every
+//! registered function up front. Functions without a target for the running
+//! architecture are not listed.
+//!
+//! # Eager Initialization
+//!
+//! Dispatchers read the host's features from one snapshot, [`capabilities()`], taken
+//! on first use. Calling [`init_all()`] at startup takes it and resolves every
+//! registered dispatcher, so the first call of a kernel in a latency-sensitive path
+//! doesn't detect anything:
+//!
+//! ```
+//! // At startup, before serving requests:
+//! multiversed::init_all();
+//! ```
+//!
+//! Autotuned functions are the exception: they measure real inputs, so they still
+//! tune on their first call.
+//!
+//! # Redetection
+//!
+//! A VM that is live-migrated can continue on a CPU with fewer features than the one
+//! its dispatchers resolved on. [`redetect()`] drops the snapshot and every resolved
+//! tier, so each function detects again on its next call. Call it where no
+//! multiversioned call is in flight. [`redetect_checkpoint()`] does so only if the host
+//! changed, as reported by the hook given to [`set_redetect_hook()`], or else by
+//! probing the features again:
+//!
+//! ```
+//! // Between requests:
+//! multiversed::redetect_checkpoint();
+//! ```
+//!
+//! Only preset features are probed again; features of raw target strings come from
+//! std's detection, which is cached for the life of the process, as is the
+//! microarchitecture `avoid_on` checks.
+//!
+//! # Call Statistics
+//!
+//! With the `stats` feature, every dispatcher counts its calls per tier, and
+//! `stats_snapshot()` reads the counters of all registered functions:
+//!
+//! ```ignore
+//! for function in multiversed::stats_snapshot() {
+//!     println!("{}: {:?}", function.path, function.calls);
+//! }
+//! // my_crate::sum: [("baseline", 12), ("x86-64-v4x", 0), ("x86-64-v3", 4096)]
+//! ```
+//!
+//! Inputs below `min_len` count as `"baseline"`. The counters are relaxed atomic
+//! increments; without the feature no counter code is generated.
+//!
+//! # Dispatch Tracing
+//!
+//! With the `trace-dispatch` feature, each dispatcher logs an `info` event through
+//! [`log`](https://docs.rs/log) (target `multiversed`) when it resolves its tier on
+//! the first call, naming the features the host lacks for each higher tier:
+//!
+//! ```text
+//! my_crate::sum: dispatching to x86-64-v3; x86-64-v4x lacks avx512f, avx512bw, ...
+//! ```
+//!
+//! Autotuned functions log `tuned to` instead. Functions whose best target the build
+//! already enables don't detect anything and log nothing.
+//!
+//! # Clone Names
+//!
+//! Clones are named after the function and their target, so profiles show which tier
+//! ran: `resize_h__x86_64_v4x` and `resize_h__arm64_v2` for presets, and
+//! `resize_h__baseline` for the fallback, with `-` and `.` replaced by `_`. Raw targets
+//! with up to three features spell them out (`resize_h__x86_64_avx2_fma`); longer ones
+//! use the 32-bit FNV-1a hash of the target string (`resize_h__x86_64_t57858d01`).
+//! A preset reduced by `deterministic_fp` keeps the preset's name.
+//! Clones are nested in the function, so their paths read
+//! `my_crate::resize_h::resize_h__x86_64_v3`.
+//!
+//! # Build Report
+//!
+//! When `MULTIVERSED_REPORT` names a file at compile time, each `#[multiversed]`
+//! expansion appends one JSON line to it:
+//!
+//! ```text
+//! {"crate":"my_crate","module":"my_crate","file":"src/lib.rs","line":7,"column":8,
+//!  "function":"sum","args":"\"x86-64-v3\", tokens","defaults":false,
+//!  "x86_64":["x86_64+sse+..."],"aarch64":[]}
+//! ```
+//!
+//! `args` are the attribute arguments as written, `defaults` tells whether the targets
+//! came from cargo features, and `x86_64` and `aarch64` are the resolved target
+//! strings, empty under `force-disable`. `module` is the module path of the function's
+//! file by cargo's layout (`my_crate::kernels` for `src/kernels.rs`); a proc macro
+//! can't see inline `mod` blocks or `#[path]` attributes. A crate compiled more than
+//! once (library, test harness, rustdoc) appends a record each time.
+//!
+//! Workspace crates are rebuilt when the variable changes, but dependencies from a
+//! registry aren't, so run `cargo clean` before a build meant to report every function. A relative path is resolved against the
+//! directory rustc runs in, which is the package's own directory for registry
+//! dependencies; use an absolute path to collect them into one file.
+//!
+//! # Preset Strings
+//!
+//! [`targets!`] and [`features!`] expand a preset name to a string literal, for code
+//! that spells out targets itself:
+//!
+//! ```
+//! const V3: &str = multiversed::features!("x86-64-v3");
+//! assert!(V3.starts_with("sse,sse2,") && V3.ends_with(",movbe"));
+//! assert!(multiversed::targets!("arm64").starts_with("aarch64+neon+"));
+//! ```
+//!
+//! `targets!` gives the `arch+feature+...` form used by the attribute and multiversion,
+//! `features!` the comma-separated form used by `#[target_feature(enable = ...)]`.
+//! rustc does not expand macros inside attribute arguments, so they cannot be written
+//! directly in `#[target_feature(...)]` or `#[multiversion(targets(...))]`; use
+//! [`macro@target_feature`] for the former.
+//!
+//! # Runtime Checks
+//!
+//! [`detect!`] expands to the complete runtime check for one preset: each of its features
+//! is enabled at compile time or present in the [`capabilities()`] snapshot, the same
+//! check the dispatchers make. After [`redetect()`], the next `detect!` takes a fresh
+//! snapshot, so it agrees with the tiers dispatch picks next rather than with std's
+//! `is_x86_feature_detected!`, which keeps its first answer for the life of the process.
+//! It is `false` on architectures the preset does not cover, and on wasm32 it reports
+//! whether the program was compiled with `simd128`:
+//!
+//! ```
+//! if multiversed::detect!("x86-64-v4x") {
+//!     // every AVX-512 feature of x86-64-v4x is available
+//! }
+//! ```
+//!
+//! It accepts `crate = "path"` like the attribute. For presets chosen at runtime, use
+//! [`Preset::is_supported`].
+//!
+//! # Compile-Time Checks
+//!
+//! [`macro@cfg_preset`] keeps an item only when the build already enables every feature
+//! of a preset (for example with `-C target-cpu=x86-64-v3`), and
+//! [`cfg_preset_enabled!`] is the matching `cfg!`. An attribute and a function-like
+//! macro cannot share a name, hence the two names:
+//!
+//! ```
+//! #[multiversed::cfg_preset("x86-64-v3")]
+//! mod avx2_baseline {
+//!     // only compiled when AVX2, FMA, ... are enabled at compile time
+//! }
+//!
+//! if multiversed::cfg_preset_enabled!("x86-64-v3") {
+//!     // no runtime detection needed
+//! }
+//! ```
+//!
+//! Build scripts can get the same answer from the `multiversed-build` crate, which sets a
+//! `multiversed_baseline_<preset>` cfg (such as `multiversed_baseline_x86_64_v3`) for
+//! each preset the target meets.
+//!
+//! # Hand-Written Kernels
+//!
+//! `#[multiversed::target_feature("x86-64-v3")]` enables a preset's features on a
+//! function, as `#[target_feature(enable = "...")]` under a `cfg_attr` for the preset's
+//! architecture. Give at most one preset per architecture. A function compiled for an
+//! architecture none of its presets covers is a compile error, so gate single-arch
+//! kernels with `#[cfg(target_arch = ...)]`:
+//!
+//! ```ignore
+//! #[cfg(target_arch = "x86_64")]
+//! #[multiversed::target_feature("x86-64-v3")]
+//! unsafe fn add_avx2(a: __m256, b: __m256) -> __m256 {
+//!     _mm256_add_ps(a, b)
+//! }
+//! ```
+//!
+//! Write the path out: a `use multiversed::target_feature` would clash with the
+//! built-in attribute.
+//!
+//! # Re-exporting
+//!
+//! The generated code reaches its runtime support as `::multiversed::__private`. A
+//! crate that re-exports `multiversed` for downstream crates without a direct
+//! dependency re-exports the crate as well, and passes its path instead, like serde's
+//! `#[serde(crate = "...")]`:
+//!
+//! ```ignore
+//! // In `imgsimd`:
+//! pub use multiversed::multiversed;
+//! #[doc(hidden)]
+//! pub use multiversed as __mv;
+//!
+//! // Downstream of `imgsimd`:
+//! #[imgsimd::multiversed(crate = "::imgsimd::__mv", "x86-64-v3")]
+//! pub fn sum(data: &[f32]) -> f32 {
+//!     data.iter().sum()
+//! }
+//! ```
 
-#![allow(dead_code)]
-
-use proc_macro::TokenStream;
-use quote::quote;
-use syn::parse::{Parse, ParseStream};
-use syn::{ItemFn, LitStr, Token, parse_macro_input};
-
-// ============================================================================
-// Target string definitions (preset name -> multiversion target string)
-//
-// Feature lists match archmage token-registry.toml — the source of truth.
-// ============================================================================
-
-// x86-64-v2: SSE4.2 + POPCNT + CX16 (Nehalem 2008+, Bulldozer 2011+)
-// Matches archmage X64V2Token
-const X86_64_V2: &str = "x86_64+sse+sse2+sse3+ssse3+sse4.1+sse4.2+popcnt+cmpxchg16b";
-
-// x86-64-v3: AVX2 + FMA (Haswell 2013+, Zen 1 2017+)
-// Matches archmage X64V3Token
-const X86_64_V3: &str = "x86_64+sse+sse2+sse3+ssse3+sse4.1+sse4.2+popcnt+cmpxchg16b+avx+avx2+fma+bmi1+bmi2+f16c+lzcnt+movbe";
-
-// x86-64-v4: AVX-512 (Skylake-X 2017+, Zen 4 2022+)
-// Matches archmage X64V4Token — pure psABI v4: F+CD+VL+DQ+BW only
-const X86_64_V4: &str = "x86_64+sse+sse2+sse3+ssse3+sse4.1+sse4.2+popcnt+cmpxchg16b+avx+avx2+fma+bmi1+bmi2+f16c+lzcnt+movbe+avx512f+avx512bw+avx512cd+avx512dq+avx512vl";
-
-// x86-64-v4-modern: Full modern AVX-512 (Ice Lake 2019+, Zen 4 2022+)
-// Matches archmage X64V4xToken — adds VNNI, VBMI2, BITALG, GFNI, VAES, VPCLMULQDQ
-const X86_64_V4_MODERN: &str = "x86_64+sse+sse2+sse3+ssse3+sse4.1+sse4.2+popcnt+cmpxchg16b+avx+avx2+fma+bmi1+bmi2+f16c+lzcnt+movbe+avx512f+avx512bw+avx512cd+avx512dq+avx512vl+avx512vpopcntdq+avx512ifma+avx512vbmi+avx512vbmi2+avx512bitalg+avx512vnni+vpclmulqdq+gfni+vaes";
-
-// arm64 / arm64-v2: Modern ARM baseline (Cortex-A55+, Apple M1+, Graviton 2+)
-// Matches archmage Arm64V2Token. "arm64" is an alias for backwards compatibility.
-const ARM64_V2: &str = "aarch64+neon+crc+rdm+dotprod+fp16+aes+sha2";
-
-// arm64-v3: Full modern ARM SIMD (Cortex-A510+, Apple M2+, Graviton 3+)
-// Matches archmage Arm64V3Token
-const ARM64_V3: &str = "aarch64+neon+crc+rdm+dotprod+fp16+aes+sha2+fhm+fcma+sha3+i8mm+bf16";
-
-// Note: wasm32 has no runtime feature detection and multiversion doesn't support it.
-// The wasm32-simd128 feature exists for documentation but generates no multiversion code.
-// Users must compile with -C target-feature=+simd128 for SIMD on wasm32.
-
-// ============================================================================
-// Preset name resolution
-// ============================================================================
-
-/// Resolve a preset name to its target string, or return the input as-is if it's a raw target.
-fn resolve_target(s: &str) -> Option<&str> {
-    match s {
-        // x86 presets (psABI standard)
-        "x86-64-v2" => Some(X86_64_V2),
-        "x86-64-v3" => Some(X86_64_V3),
-        "x86-64-v4" => Some(X86_64_V4),
-        "x86-64-v4-modern" | "x86-64-v4x" => Some(X86_64_V4_MODERN),
-        // aarch64 presets ("arm64" is an alias for "arm64-v2")
-        "arm64" | "arm64-v2" => Some(ARM64_V2),
-        "arm64-v3" => Some(ARM64_V3),
-        // wasm32 - multiversion doesn't support it, ignore
-        "wasm32-simd128" => None,
-        // Raw target string - pass through if it looks like a valid target
-        s if s.contains('+') && !s.starts_with("wasm32") => Some(s),
-        // Unknown or wasm32 raw target - ignore
-        _ => None,
-    }
-}
-
-/// Check if a target string is for x86/x86_64 architecture.
-fn is_x86_target(s: &str) -> bool {
-    s.starts_with("x86_64+") || s.starts_with("x86+") || s.starts_with("x86-64-")
-}
-
-/// Check if a target string is for aarch64 architecture.
-fn is_aarch64_target(s: &str) -> bool {
-    s.starts_with("aarch64+") || s.starts_with("aarch64-")
-}
-
-// ============================================================================
-// Attribute argument parsing
-// ============================================================================
-
-struct MultiversedArgs {
-    targets: Vec<String>,
-}
-
-impl Parse for MultiversedArgs {
-    fn parse(input: ParseStream) -> syn::Result<Self> {
-        let mut targets = Vec::new();
-
-        while !input.is_empty() {
-            let lit: LitStr = input.parse()?;
-            targets.push(lit.value());
-
-            if input.peek(Token![,]) {
-                let _: Token![,] = input.parse()?;
-            }
-        }
-
-        Ok(MultiversedArgs { targets })
-    }
-}
-
-// ============================================================================
-// Default targets from cargo features
-// ============================================================================
-
-// Vec init-then-push pattern required for conditional cfg compilation.
-#[allow(clippy::vec_init_then_push)]
-fn default_x86_targets() -> Vec<&'static str> {
-    let mut targets = Vec::new();
-
-    // Higher tiers first (more specific optimizations)
-    #[cfg(any(feature = "x86-64-v4x", feature = "x86-64-v4-modern"))]
-    targets.push(X86_64_V4_MODERN);
-
-    #[cfg(feature = "x86-64-v4")]
-    targets.push(X86_64_V4);
-
-    #[cfg(feature = "x86-64-v3")]
-    targets.push(X86_64_V3);
-
-    #[cfg(feature = "x86-64-v2")]
-    targets.push(X86_64_V2);
-
-    targets
-}
-
-#[allow(clippy::vec_init_then_push)]
-fn default_aarch64_targets() -> Vec<&'static str> {
-    let mut targets = Vec::new();
-
-    // Higher tiers first (more specific optimizations)
-    #[cfg(feature = "arm64-v3")]
-    targets.push(ARM64_V3);
-
-    // "arm64" is an alias for "arm64-v2" — both activate the arm64-v2 feature,
-    // so we only check arm64-v2 here to avoid duplicate targets.
-    #[cfg(feature = "arm64-v2")]
-    targets.push(ARM64_V2);
-
-    targets
-}
-
-// ============================================================================
-// Main attribute macro
-// ============================================================================
-
-/// Apply multiversion with SIMD target presets.
-///
-/// # Usage
-///
-/// ```ignore
-/// use multiversed::multiversed;
-///
-/// // Use cargo feature defaults
-/// #[multiversed]
-/// fn sum(data: &[f32]) -> f32 {
-///     data.iter().sum()
-/// }
-///
-/// // Explicit presets
-/// #[multiversed("x86-64-v4", "arm64-v2")]
-/// fn optimized(data: &[f32]) -> f32 {
-///     data.iter().sum()
-/// }
-///
-/// // Mix presets with raw target strings
-/// #[multiversed("x86-64-v3", "x86_64+avx2+fma+custom")]
-/// fn custom(data: &[f32]) -> f32 {
-///     data.iter().sum()
-/// }
-/// ```
-///
-/// # Arguments
-///
-/// - **No arguments**: Uses targets from enabled cargo features
-/// - **Preset names**: `"x86-64-v3"`, `"arm64"`, etc.
-/// - **Raw target strings**: Any string with `+` is passed through to multiversion
-#[proc_macro_attribute]
-pub fn multiversed(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let func = parse_macro_input!(item as ItemFn);
-
-    // force-disable feature: passthrough without any multiversion
-    #[cfg(feature = "force-disable")]
-    {
-        let _ = attr; // suppress unused warning
-        #[allow(clippy::needless_return)]
-        return quote! { #func }.into();
-    }
-
-    #[cfg(not(feature = "force-disable"))]
-    {
-        let args = parse_macro_input!(attr as MultiversedArgs);
-        multiversed_impl(args, func)
-    }
+pub use multiversed_macros::{
+    cfg_preset, cfg_preset_enabled, detect, features, multiversed, target_feature, targets,
+};
+pub use multiversed_rt::{
+    Arch, Capabilities, Function, ParsePresetError, Preset, capabilities, init_all, redetect,
+    redetect_checkpoint, registry, set_redetect_hook,
+};
+#[cfg(feature = "stats")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stats")))]
+pub use multiversed_rt::{FunctionStats, stats_snapshot};
+
+/// What the running copy of a `#[multiversed]` function's body is compiled for.
+pub mod target {
+    pub use multiversed_macros::{match_target, selected_target};
+    #[doc(no_inline)]
+    pub use target_features::Target;
 }
 
-fn multiversed_impl(args: MultiversedArgs, func: ItemFn) -> TokenStream {
-    // Collect targets, separating by architecture
-    let (x86_targets, aarch64_targets) = if args.targets.is_empty() {
-        // No explicit targets - use cargo feature defaults
-        let x86: Vec<String> = default_x86_targets()
-            .into_iter()
-            .map(String::from)
-            .collect();
-        let aarch64: Vec<String> = default_aarch64_targets()
-            .into_iter()
-            .map(String::from)
-            .collect();
-        (x86, aarch64)
-    } else {
-        // Explicit targets - resolve presets and partition by architecture.
-        // Deduplicate because aliases (e.g., "arm64" and "arm64-v2") resolve to the
-        // same target string, and multiversion would generate duplicate function versions.
-        // Note: wasm32 targets are filtered out (multiversion doesn't support wasm32)
-        let mut resolved: Vec<String> = Vec::new();
-        for s in &args.targets {
-            if let Some(target) = resolve_target(s) {
-                let target = target.to_string();
-                if !resolved.contains(&target) {
-                    resolved.push(target);
-                }
-            }
-        }
-
-        let x86: Vec<String> = resolved
-            .iter()
-            .filter(|s| is_x86_target(s))
-            .cloned()
-            .collect();
-        let aarch64: Vec<String> = resolved
-            .iter()
-            .filter(|s| is_aarch64_target(s))
-            .cloned()
-            .collect();
-
-        (x86, aarch64)
-    };
-
-    // Build cfg_attr for each architecture that has targets
-    let x86_attr = if x86_targets.is_empty() {
-        quote! {}
-    } else {
-        quote! {
-            #[cfg_attr(
-                any(target_arch = "x86", target_arch = "x86_64"),
-                multiversion::multiversion(targets(#(#x86_targets),*))
-            )]
-        }
-    };
-
-    let aarch64_attr = if aarch64_targets.is_empty() {
-        quote! {}
-    } else {
-        quote! {
-            #[cfg_attr(
-                target_arch = "aarch64",
-                multiversion::multiversion(targets(#(#aarch64_targets),*))
-            )]
-        }
-    };
-
-    quote! {
-        #x86_attr
-        #aarch64_attr
-        #func
-    }
-    .into()
-}
+#[doc(hidden)]
+#[path = "private.rs"]
+pub mod __private;
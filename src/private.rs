@@ -0,0 +1,333 @@
+//! Runtime support for code generated by `#[multiversed]`. Not public API.
+//!
+//! The macro refers to everything through `::multiversed::__private`, or the path given
+//! as `crate = "..."` in place of `::multiversed`, so user crates only need to depend on
+//! `multiversed`.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+#[cfg(target_arch = "aarch64")]
+pub use std::arch::is_aarch64_feature_detected;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub use std::arch::is_x86_feature_detected;
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+pub use multiversed_rt::{FUNCTIONS, linkme};
+pub use multiversed_rt::{Function, has_feature};
+
+pub use multiversed_macros::__match_target as match_target;
+pub use multiversed_rt::Preset;
+pub use target_features;
+
+/// Warnings of `deterministic_fp`, raised by using these constants.
+pub mod deterministic_fp {
+    #[deprecated(
+        note = "`deterministic_fp` keeps FMA out of every tier, so this fused multiply-add runs in software or needs a feature the clones don't enable; write `a * b + c` unless the fused result is wanted"
+    )]
+    pub const FUSED_MULTIPLY_ADD: () = ();
+
+    #[deprecated(
+        note = "`deterministic_fp` dropped the AVX-512 tiers enabled by cargo features, since AVX-512 implies FMA; list the targets to silence this"
+    )]
+    pub const AVX512_TIERS_DROPPED: () = ();
+}
+
+// ============================================================================
+// CPU identification
+// ============================================================================
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn cpuid(leaf: u32) -> [u32; 4] {
+    #[cfg(target_arch = "x86")]
+    use core::arch::x86::__cpuid;
+    #[cfg(target_arch = "x86_64")]
+    use core::arch::x86_64::__cpuid;
+
+    // SAFETY: every CPU that can run a multiversioned x86 clone has cpuid.
+    #[allow(unused_unsafe, unsafe_code)]
+    let r = unsafe { __cpuid(leaf) };
+    [r.eax, r.ebx, r.ecx, r.edx]
+}
+
+/// The CPUID brand string, e.g. `AMD Ryzen 9 7950X 16-Core Processor`. x86 only.
+pub fn cpu_model() -> Option<&'static str> {
+    static MODEL: OnceLock<Option<String>> = OnceLock::new();
+    MODEL.get_or_init(detect_cpu_model).as_deref()
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn detect_cpu_model() -> Option<String> {
+    if cpuid(0x8000_0000)[0] < 0x8000_0004 {
+        return None;
+    }
+    let bytes: Vec<u8> = (0x8000_0002..=0x8000_0004)
+        .flat_map(cpuid)
+        .flat_map(u32::to_le_bytes)
+        .collect();
+    let model = String::from_utf8_lossy(&bytes)
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string();
+    (!model.is_empty()).then_some(model)
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn detect_cpu_model() -> Option<String> {
+    None
+}
+
+// ============================================================================
+// Microarchitecture policy (avoid_on)
+// ============================================================================
+
+/// Whether the host CPU is one of the `avoid_on` microarchitectures in `names`.
+pub fn on_microarch(names: &[&str]) -> bool {
+    static MICROARCH: OnceLock<Option<&'static str>> = OnceLock::new();
+    MICROARCH
+        .get_or_init(detect_microarch)
+        .is_some_and(|microarch| names.contains(&microarch))
+}
+
+/// Identify the host's `avoid_on` microarchitecture through CPUID.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+fn detect_microarch() -> Option<&'static str> {
+    let [_, ebx, ecx, edx] = cpuid(0);
+    let mut vendor = [0; 12];
+    for (bytes, reg) in vendor.chunks_exact_mut(4).zip([ebx, edx, ecx]) {
+        bytes.copy_from_slice(&reg.to_le_bytes());
+    }
+    classify(&vendor, cpuid(1)[0])
+}
+
+/// Identify the `avoid_on` microarchitecture from the CPUID vendor string and the
+/// processor signature (leaf 1 EAX).
+#[cfg_attr(
+    not(any(target_arch = "x86", target_arch = "x86_64")),
+    allow(dead_code)
+)]
+fn classify(vendor: &[u8; 12], signature: u32) -> Option<&'static str> {
+    let intel = vendor == b"GenuineIntel";
+    let amd = vendor == b"AuthenticAMD";
+
+    let base_family = (signature >> 8) & 0xF;
+    let family = if base_family == 0xF {
+        base_family + ((signature >> 20) & 0xFF)
+    } else {
+        base_family
+    };
+    let model = if base_family == 0x6 || base_family == 0xF {
+        ((signature >> 12) & 0xF0) | ((signature >> 4) & 0xF)
+    } else {
+        (signature >> 4) & 0xF
+    };
+
+    match (intel, amd, family, model) {
+        // Skylake-SP/X, Cascade Lake and Cooper Lake share model 0x55.
+        (true, _, 0x6, 0x55) => Some("skylake-x"),
+        (true, _, 0x6, 0x6A | 0x6C | 0x7D | 0x7E) => Some("icelake"),
+        // Family 0x19 is shared with Zen 3; Genoa, Raphael, Phoenix and Bergamo models.
+        (_, true, 0x19, 0x10..=0x1F | 0x60..=0x7F | 0xA0..=0xAF) => Some("zen4"),
+        (_, true, 0x1A, _) => Some("zen5"),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+fn detect_microarch() -> Option<&'static str> {
+    None
+}
+
+// ============================================================================
+// Dispatch tracing (trace-dispatch)
+// ============================================================================
+
+/// Log the tier a dispatcher resolved to, once, with what kept it off the higher tiers.
+///
+/// `selected` indexes like the registry: 0 for the fallback, `i` for `targets[i - 1]`.
+/// `features` has each target's features with whether the host has them.
+#[cfg(feature = "trace-dispatch")]
+#[cold]
+pub fn trace_dispatch(
+    path: &str,
+    tuned: bool,
+    selected: usize,
+    targets: &[&str],
+    features: &[&[(&str, bool)]],
+) {
+    let message = dispatch_message(path, tuned, selected, targets, features);
+    log::info!(target: "multiversed", "{message}");
+}
+
+/// The message of [`trace_dispatch`]. A higher tier without missing features was
+/// skipped by `avoid_on`, or measured slower when `tuned`.
+#[cfg(feature = "trace-dispatch")]
+fn dispatch_message(
+    path: &str,
+    tuned: bool,
+    selected: usize,
+    targets: &[&str],
+    features: &[&[(&str, bool)]],
+) -> String {
+    use std::fmt::Write;
+
+    let chosen = selected.checked_sub(1).map_or("baseline", |i| targets[i]);
+    let higher = selected.checked_sub(1).unwrap_or(targets.len());
+    let mut message = format!(
+        "{path}: {} {chosen}",
+        if tuned { "tuned to" } else { "dispatching to" }
+    );
+    for (target, features) in targets.iter().zip(features).take(higher) {
+        let missing: Vec<&str> = features
+            .iter()
+            .filter(|(_, detected)| !detected)
+            .map(|(feature, _)| *feature)
+            .collect();
+        let _ = if !missing.is_empty() {
+            write!(message, "; {target} lacks {}", missing.join(", "))
+        } else if tuned {
+            write!(message, "; {target} measured slower")
+        } else {
+            write!(message, "; {target} avoided on this CPU")
+        };
+    }
+    message
+}
+
+// ============================================================================
+// First-call autotuning (autotune)
+// ============================================================================
+
+/// Fastest per-call time of `run` over a few rounds of at least 200µs each.
+#[cold]
+pub fn time(mut run: impl FnMut()) -> Duration {
+    run();
+    let mut best = Duration::MAX;
+    for _ in 0..5 {
+        let start = Instant::now();
+        let mut calls = 0u32;
+        while {
+            run();
+            calls += 1;
+            start.elapsed() < Duration::from_micros(200)
+        } {}
+        best = best.min(start.elapsed() / calls);
+    }
+    best
+}
+
+/// Look up the tier for `key` in the autotune cache, or run `tune` and record its choice.
+///
+/// The cache is the text file named by `MULTIVERSED_AUTOTUNE_CACHE`, one
+/// `cpu model<TAB>function<TAB>tier` line per result, where the tier is `baseline` for
+/// index 0 and `targets[i - 1]` for index `i`. It is only used where [`cpu_model`] is
+/// known. A cached tier is ignored unless `detected` accepts it.
+#[cold]
+pub fn autotune(
+    key: &str,
+    targets: &[&str],
+    detected: &dyn Fn(usize) -> bool,
+    tune: &mut dyn FnMut() -> usize,
+) -> usize {
+    use std::io::Write;
+
+    let (Some(path), Some(model)) = (std::env::var_os("MULTIVERSED_AUTOTUNE_CACHE"), cpu_model())
+    else {
+        return tune();
+    };
+    let cached = std::fs::read_to_string(&path).ok().and_then(|contents| {
+        let label = contents.lines().rev().find_map(|line| {
+            let mut fields = line.split('\t');
+            if fields.next()? == model && fields.next()? == key {
+                fields.next()
+            } else {
+                None
+            }
+        })?;
+        if label == "baseline" {
+            Some(0)
+        } else {
+            targets.iter().position(|t| *t == label).map(|i| i + 1)
+        }
+    });
+    if let Some(index) = cached.filter(|&index| detected(index)) {
+        return index;
+    }
+
+    let index = tune();
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+    {
+        let label = index.checked_sub(1).map_or("baseline", |i| targets[i]);
+        let _ = writeln!(file, "{model}\t{key}\t{label}");
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "trace-dispatch")]
+    const TARGETS: &[&str] = &["x86-64-v4x", "x86-64-v3"];
+    #[cfg(feature = "trace-dispatch")]
+    const FEATURES: &[&[(&str, bool)]] = &[
+        &[("avx2", true), ("avx512f", false), ("gfni", false)],
+        &[("avx2", true), ("fma", true)],
+    ];
+
+    #[test]
+    #[cfg(feature = "trace-dispatch")]
+    fn dispatch_message_lists_missing_features() {
+        assert_eq!(
+            dispatch_message("k::sum", false, 2, TARGETS, FEATURES),
+            "k::sum: dispatching to x86-64-v3; x86-64-v4x lacks avx512f, gfni"
+        );
+        assert_eq!(
+            dispatch_message("k::sum", false, 1, TARGETS, FEATURES),
+            "k::sum: dispatching to x86-64-v4x"
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "trace-dispatch")]
+    fn dispatch_message_explains_skipped_tiers() {
+        let features: &[&[(&str, bool)]] = &[&[("avx512f", true)], &[("avx2", true)]];
+        assert_eq!(
+            dispatch_message("k::sum", false, 2, TARGETS, features),
+            "k::sum: dispatching to x86-64-v3; x86-64-v4x avoided on this CPU"
+        );
+        assert_eq!(
+            dispatch_message("k::sum", true, 0, TARGETS, features),
+            "k::sum: tuned to baseline; x86-64-v4x measured slower; x86-64-v3 measured slower"
+        );
+    }
+
+    #[test]
+    fn classify_avoid_on_microarchitectures() {
+        let intel = b"GenuineIntel";
+        let amd = b"AuthenticAMD";
+        // Zen 4 (Raphael), Skylake-SP, Ice Lake-SP, Ice Lake client.
+        assert_eq!(classify(amd, 0xA60F12), Some("zen4"));
+        assert_eq!(classify(intel, 0x50654), Some("skylake-x"));
+        assert_eq!(classify(intel, 0x606A6), Some("icelake"));
+        assert_eq!(classify(intel, 0x706E5), Some("icelake"));
+        // Zen 5 (Granite Ridge).
+        assert_eq!(classify(amd, 0xB40F40), Some("zen5"));
+    }
+
+    #[test]
+    fn classify_leaves_others_alone() {
+        let intel = b"GenuineIntel";
+        let amd = b"AuthenticAMD";
+        // Haswell, Sapphire Rapids, Zen 3 (Vermeer).
+        assert_eq!(classify(intel, 0x306C3), None);
+        assert_eq!(classify(intel, 0x806F8), None);
+        assert_eq!(classify(amd, 0xA20F10), None);
+        // A known signature under the wrong or an unknown vendor.
+        assert_eq!(classify(amd, 0x50654), None);
+        assert_eq!(classify(intel, 0xA60F12), None);
+        assert_eq!(classify(b"HygonGenuine", 0xA60F12), None);
+    }
+}
@@ -0,0 +1,3 @@
+fn main() {
+    multiversed_build::emit_preset_cfgs();
+}
@@ -0,0 +1,46 @@
+//! Test crate whose build script sets `multiversed_baseline_*` cfgs.
+
+/// The preset names whose baseline cfg the build script set.
+pub fn baseline() -> Vec<&'static str> {
+    #[allow(unused_mut)]
+    let mut presets = Vec::new();
+    #[cfg(multiversed_baseline_x86_64_v2)]
+    presets.push("x86-64-v2");
+    #[cfg(multiversed_baseline_x86_64_v3)]
+    presets.push("x86-64-v3");
+    #[cfg(multiversed_baseline_x86_64_v4)]
+    presets.push("x86-64-v4");
+    #[cfg(multiversed_baseline_x86_64_v4x)]
+    presets.push("x86-64-v4x");
+    #[cfg(multiversed_baseline_arm64_v2)]
+    presets.push("arm64-v2");
+    #[cfg(multiversed_baseline_arm64_v3)]
+    presets.push("arm64-v3");
+    #[cfg(multiversed_baseline_wasm32_simd128)]
+    presets.push("wasm32-simd128");
+    presets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multiversed::cfg_preset_enabled;
+
+    #[test]
+    fn test_build_cfgs_match_cfg_preset() {
+        let expected: Vec<&str> = [
+            ("x86-64-v2", cfg_preset_enabled!("x86-64-v2")),
+            ("x86-64-v3", cfg_preset_enabled!("x86-64-v3")),
+            ("x86-64-v4", cfg_preset_enabled!("x86-64-v4")),
+            ("x86-64-v4x", cfg_preset_enabled!("x86-64-v4x")),
+            ("arm64-v2", cfg_preset_enabled!("arm64-v2")),
+            ("arm64-v3", cfg_preset_enabled!("arm64-v3")),
+            ("wasm32-simd128", cfg_preset_enabled!("wasm32-simd128")),
+        ]
+        .into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| name)
+        .collect();
+        assert_eq!(baseline(), expected);
+    }
+}
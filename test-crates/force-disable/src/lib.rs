@@ -1,12 +1,12 @@
 //! Test crate using multiversed with force-disable feature
 //!
 //! When force-disable is enabled, ALL `#[multiversed]` attributes become pure
-//! passthroughs - no multiversion code is generated, regardless of other features
+//! passthroughs - no clones are generated, regardless of other features
 //! or explicit arguments. This is useful for debugging or faster builds.
 
 use multiversed::multiversed;
 
-/// With force-disable, this is a plain function (no multiversion)
+/// With force-disable, this is a plain function (no clones)
 #[multiversed]
 pub fn sum_array(data: &[f32]) -> f32 {
     data.iter().sum()
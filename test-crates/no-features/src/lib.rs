@@ -2,7 +2,7 @@
 //!
 //! With no presets enabled, functions using the bare `#[multiversed]` attribute
 //! become passthroughs. Only explicit preset arguments or raw target strings
-//! will generate multiversion code.
+//! will generate multiversioned code.
 
 use multiversed::multiversed;
 
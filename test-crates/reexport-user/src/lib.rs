@@ -0,0 +1,66 @@
+//! Test crate using multiversed only through `test-reexport`, with `crate = "..."`.
+
+use test_reexport::{detect, multiversed};
+
+/// Tiered dispatch through the re-exported path
+#[multiversed(crate = "::test_reexport::__mv")]
+pub fn sum(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+/// Dispatch policies refer to the runtime support module too
+#[multiversed(
+    "x86-64-v4x",
+    "x86-64-v3",
+    crate = "::test_reexport::__mv",
+    min_len = 8,
+    len = data,
+    avoid_on = ["zen4"]
+)]
+pub fn max(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+/// Autotuning calls the timing and cache helpers through the same path
+#[multiversed(crate = "::test_reexport::__mv", autotune)]
+pub fn dot_product(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// `detect!` takes the same path
+pub fn has_v3() -> bool {
+    detect!("x86-64-v3", crate = "::test_reexport::__mv")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum() {
+        assert_eq!(sum(&[1.0, 2.0, 3.0, 4.0]), 10.0);
+    }
+
+    #[test]
+    fn test_max() {
+        assert_eq!(max(&[1.0, 5.0, 3.0]), 5.0);
+        let long: Vec<f32> = (0..64).map(|i| i as f32).collect();
+        assert_eq!(max(&long), 63.0);
+    }
+
+    #[test]
+    fn test_has_v3() {
+        #[cfg(target_arch = "x86_64")]
+        if has_v3() {
+            assert!(std::arch::is_x86_feature_detected!("avx2"));
+            assert!(std::arch::is_x86_feature_detected!("fma"));
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        assert!(!has_v3());
+    }
+
+    #[test]
+    fn test_dot_product() {
+        assert_eq!(dot_product(&[1.0, 2.0], &[3.0, 4.0]), 11.0);
+    }
+}
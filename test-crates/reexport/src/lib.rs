@@ -0,0 +1,9 @@
+//! Test crate re-exporting multiversed under another path, as a SIMD helper crate would.
+//!
+//! Crates downstream of this one (see `test-reexport-user`) do not depend on
+//! multiversed themselves and pass `crate = "::test_reexport::__mv"`
+//! to the attribute.
+
+#[doc(hidden)]
+pub use multiversed as __mv;
+pub use multiversed::{detect, multiversed};
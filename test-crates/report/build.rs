@@ -0,0 +1,11 @@
+//! Points `MULTIVERSED_REPORT` at a fresh file in `OUT_DIR` for this crate's expansions.
+//!
+//! Without `rerun-if-changed`, this reruns whenever the crate's sources change, which
+//! also recompiles them, so the file never keeps records of earlier builds.
+
+fn main() {
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let report = std::path::Path::new(&out_dir).join("multiversed.json");
+    let _ = std::fs::remove_file(&report);
+    println!("cargo:rustc-env=MULTIVERSED_REPORT={}", report.display());
+}
@@ -0,0 +1,9 @@
+//! A function in a module of its own file.
+
+use multiversed::multiversed;
+
+/// Multiplies each element by `factor`
+#[multiversed("x86-64-v3")]
+pub fn scale(data: &[f32], factor: f32) -> Vec<f32> {
+    data.iter().map(|x| x * factor).collect()
+}
@@ -0,0 +1,100 @@
+//! Test crate compiled with `MULTIVERSED_REPORT` set by its build script.
+
+use multiversed::multiversed;
+
+pub mod kernels;
+
+/// Explicit presets and an option
+#[multiversed("x86-64-v3", "arm64-v2", deterministic_fp, companion)]
+pub fn sum(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+/// Targets from the enabled cargo features
+#[multiversed]
+pub fn max(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    /// The records for `function`. The library, its test harness and rustdoc each append
+    /// one, and so do builds with other feature sets, which share the build script's
+    /// `OUT_DIR` without rerunning it.
+    fn records(function: &str) -> Vec<Value> {
+        let report = std::fs::read_to_string(concat!(env!("OUT_DIR"), "/multiversed.json"))
+            .expect("the report was written");
+        let records: Vec<Value> = report
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("each line is a JSON object"))
+            .filter(|record: &Value| record["function"] == function)
+            .collect();
+        assert!(!records.is_empty(), "no record for {function}");
+        records
+    }
+
+    /// Whether the crate was built with clones, unless a workspace build unifies in
+    /// `force-disable`, which leaves the target lists empty.
+    fn cloned() -> bool {
+        !sum::available_targets().is_empty()
+    }
+
+    #[test]
+    fn records_location_and_arguments() {
+        for record in records("sum") {
+            assert_eq!(record["crate"], "test_report");
+            assert_eq!(record["module"], "test_report");
+            assert!(record["file"].as_str().unwrap().ends_with("src/lib.rs"));
+            assert_eq!(record["line"], 9);
+            assert_eq!(record["column"], 8);
+            assert_eq!(
+                record["args"],
+                r#""x86-64-v3", "arm64-v2", deterministic_fp, companion"#
+            );
+            assert_eq!(record["defaults"], false);
+        }
+        for record in records("max") {
+            assert_eq!(record["line"], 15);
+            assert_eq!(record["args"], "");
+            assert_eq!(record["defaults"], true);
+        }
+    }
+
+    #[test]
+    fn records_module_of_the_file() {
+        for record in records("scale") {
+            assert_eq!(record["module"], "test_report::kernels");
+            assert!(record["file"].as_str().unwrap().ends_with("src/kernels.rs"));
+        }
+    }
+
+    #[test]
+    fn records_resolved_targets() {
+        // `deterministic_fp` drops FMA from x86-64-v3.
+        let x86_64 = multiversed::targets!("x86-64-v3").replace("+fma", "");
+        let aarch64 = multiversed::targets!("arm64-v2");
+        let cloned_targets = (serde_json::json!([x86_64]), serde_json::json!([aarch64]));
+        let no_targets = (serde_json::json!([]), serde_json::json!([]));
+        let targets: Vec<(Value, Value)> = records("sum")
+            .into_iter()
+            .map(|record| (record["x86_64"].clone(), record["aarch64"].clone()))
+            .collect();
+        assert!(
+            targets
+                .iter()
+                .all(|found| *found == cloned_targets || *found == no_targets)
+        );
+        let this_build = if cloned() { cloned_targets } else { no_targets };
+        assert!(targets.contains(&this_build));
+    }
+
+    #[test]
+    fn reported_function_still_runs() {
+        assert_eq!(sum(&[1.0, 2.0, 3.0]), 6.0);
+        assert_eq!(max(&[1.0, 3.0, 2.0]), 3.0);
+        assert_eq!(kernels::scale(&[1.0, 2.0], 3.0), [3.0, 6.0]);
+    }
+}
@@ -0,0 +1,92 @@
+//! Test crate using multiversed with the `stats` feature.
+
+use multiversed::multiversed;
+
+/// Tiered dispatch
+#[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2", companion)]
+pub fn max(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+/// Short inputs run the fallback without dispatching
+#[multiversed("x86-64-v3", "arm64-v2", min_len = 8, len = data, companion)]
+pub fn sum(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+/// Autotuned dispatch
+#[multiversed("x86-64-v3", "arm64-v2", autotune, companion)]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use multiversed::{FunctionStats, stats_snapshot};
+
+    /// The calls per tier of the function at `path`, or `None` when nothing is counted:
+    /// when a workspace build unifies in `force-disable`, or the host has no presets.
+    fn calls(path: &str) -> Option<Vec<(&'static str, u64)>> {
+        let stats: Vec<FunctionStats> = stats_snapshot();
+        if stats.is_empty() {
+            return None;
+        }
+        let function = stats.into_iter().find(|stats| stats.path == path);
+        Some(
+            function
+                .unwrap_or_else(|| panic!("no stats for {path}"))
+                .calls,
+        )
+    }
+
+    /// `calls` with every tier at zero except `tier`.
+    fn only(tiers: &[&'static str], tier: &str, calls: u64) -> Vec<(&'static str, u64)> {
+        std::iter::once(&"baseline")
+            .chain(tiers)
+            .map(|t| (*t, if *t == tier { calls } else { 0 }))
+            .collect()
+    }
+
+    #[test]
+    fn test_counts_calls_of_the_selected_tier() {
+        let data = [1.0, 5.0, 3.0];
+        for _ in 0..3 {
+            assert_eq!(max(&data), 5.0);
+        }
+        let Some(calls) = calls("test_stats::max") else {
+            return;
+        };
+        let tiers = max::available_targets();
+        assert_eq!(calls, only(tiers, max::selected_target(), 3));
+    }
+
+    #[test]
+    fn test_short_inputs_count_as_baseline() {
+        let short = [1.0; 4];
+        let long = [1.0; 64];
+        assert_eq!(sum(&short), 4.0);
+        assert_eq!(sum(&short), 4.0);
+        assert_eq!(sum(&long), 64.0);
+        let Some(calls) = calls("test_stats::sum") else {
+            return;
+        };
+        let mut expected = only(sum::available_targets(), sum::selected_target(), 1);
+        expected[0].1 += 2;
+        assert_eq!(calls, expected);
+    }
+
+    #[test]
+    fn test_autotune_counts_calls_not_measurements() {
+        let data = [2.0; 256];
+        assert_eq!(dot(&data, &data), 1024.0);
+        assert_eq!(dot(&data, &data), 1024.0);
+        let Some(calls) = calls("test_stats::dot") else {
+            return;
+        };
+        assert_eq!(
+            calls,
+            only(dot::available_targets(), dot::selected_target(), 2)
+        );
+    }
+}
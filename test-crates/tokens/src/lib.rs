@@ -0,0 +1,85 @@
+//! Test crate using multiversed's `tokens` option with archmage.
+
+use archmage::SimdToken;
+use multiversed::multiversed;
+
+/// The name of the archmage token each clone binds
+#[multiversed(
+    tokens,
+    expose,
+    "x86-64-v4x",
+    "x86-64-v4",
+    "x86-64-v3",
+    "x86-64-v2",
+    "arm64-v3",
+    "arm64-v2"
+)]
+pub fn token_name() -> &'static str {
+    token.name()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archmage::{
+        Arm64V2Token, Arm64V3Token, ScalarToken, X64V2Token, X64V3Token, X64V4Token, X64V4xToken,
+    };
+    use multiversed::Preset;
+
+    /// Each tier of `token_name` and the token its clone should bind.
+    const TIERS: [(Preset, &str); 6] = [
+        (Preset::X86_64V4x, X64V4xToken::NAME),
+        (Preset::X86_64V4, X64V4Token::NAME),
+        (Preset::X86_64V3, X64V3Token::NAME),
+        (Preset::X86_64V2, X64V2Token::NAME),
+        (Preset::Arm64V3, Arm64V3Token::NAME),
+        (Preset::Arm64V2, Arm64V2Token::NAME),
+    ];
+
+    /// Clones exist, unless a workspace build unifies in `force-disable`.
+    fn cloned() -> bool {
+        multiversed::registry()
+            .iter()
+            .any(|function| function.path() == "test_tokens::token_name")
+    }
+
+    /// The token the fallback binds: the highest one the build enables.
+    fn fallback_token() -> &'static str {
+        let compiled = |enabled: Option<bool>| enabled == Some(true);
+        [
+            (compiled(X64V4xToken::compiled_with()), X64V4xToken::NAME),
+            (compiled(X64V4Token::compiled_with()), X64V4Token::NAME),
+            (compiled(X64V3Token::compiled_with()), X64V3Token::NAME),
+            (compiled(X64V2Token::compiled_with()), X64V2Token::NAME),
+            (compiled(Arm64V3Token::compiled_with()), Arm64V3Token::NAME),
+            (compiled(Arm64V2Token::compiled_with()), Arm64V2Token::NAME),
+        ]
+        .into_iter()
+        .find_map(|(enabled, name)| enabled.then_some(name))
+        .unwrap_or(ScalarToken::NAME)
+    }
+
+    #[test]
+    fn test_each_tier_binds_its_token() {
+        for (preset, expected) in TIERS {
+            match token_name::tiers::try_call(preset) {
+                Some(name) => assert_eq!(name, expected, "{preset}"),
+                None => assert!(!cloned() || !preset.is_supported(), "{preset}"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_fallback_binds_compiled_token() {
+        assert_eq!(token_name::tiers::baseline(), fallback_token());
+    }
+
+    #[test]
+    fn test_dispatch_binds_selected_token() {
+        let expected = TIERS
+            .into_iter()
+            .find(|(preset, _)| cloned() && preset.is_supported())
+            .map_or(fallback_token(), |(_, name)| name);
+        assert_eq!(token_name(), expected);
+    }
+}
@@ -0,0 +1,117 @@
+//! Test crate using multiversed with the `trace-dispatch` feature.
+
+use multiversed::multiversed;
+
+/// Tiered dispatch, with a raw target few CPUs have on top
+#[multiversed(
+    "x86_64+avx512f+avx512fp16",
+    "x86-64-v3",
+    "arm64-v3",
+    "arm64-v2",
+    companion
+)]
+pub fn max(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+/// Autotuned dispatch
+#[multiversed("x86-64-v3", "arm64-v2", autotune, companion)]
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Logger keeping the messages of the `multiversed` target.
+    struct Capture(Mutex<Vec<String>>);
+
+    impl log::Log for Capture {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "multiversed"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    /// The messages logged about the function at `path`, or `None` when nothing is
+    /// dispatched: when a workspace build unifies in `force-disable`, or no target is
+    /// compiled for the host.
+    fn messages(path: &str) -> Option<Vec<String>> {
+        if multiversed::registry().is_empty() {
+            return None;
+        }
+        let prefix = format!("{path}: ");
+        let messages = CAPTURE.0.lock().unwrap();
+        Some(
+            messages
+                .iter()
+                .filter(|m| m.starts_with(&prefix))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    fn install() {
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(log::LevelFilter::Info);
+    }
+
+    #[test]
+    fn test_logs_resolution_once() {
+        install();
+        let data = [1.0, 5.0, 3.0];
+        assert_eq!(max(&data), 5.0);
+        assert_eq!(max(&data), 5.0);
+        let Some(messages) = messages("test_trace_dispatch::max") else {
+            return;
+        };
+        assert_eq!(messages.len(), 1, "{messages:?}");
+        let message = &messages[0];
+        let selected = max::selected_target();
+        assert!(
+            message.contains(&format!("dispatching to {selected}")),
+            "{message}"
+        );
+
+        // Every tier above the selected one says what it lacks.
+        let targets = max::available_targets();
+        let higher = targets
+            .iter()
+            .position(|t| *t == selected)
+            .unwrap_or(targets.len());
+        for target in &targets[..higher] {
+            assert!(message.contains(&format!("; {target} lacks ")), "{message}");
+        }
+        for target in &targets[higher..] {
+            assert!(!message.contains(&format!("; {target} ")), "{message}");
+        }
+    }
+
+    #[test]
+    fn test_logs_autotune_choice() {
+        install();
+        let data = [2.0; 256];
+        assert_eq!(dot(&data, &data), 1024.0);
+        assert_eq!(dot(&data, &data), 1024.0);
+        let Some(messages) = messages("test_trace_dispatch::dot") else {
+            return;
+        };
+        let selected = dot::selected_target();
+        assert_eq!(messages.len(), 1, "{messages:?}");
+        assert!(
+            messages[0].contains(&format!("tuned to {selected}")),
+            "{messages:?}"
+        );
+    }
+}
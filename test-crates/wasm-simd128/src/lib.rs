@@ -1,6 +1,6 @@
 //! Test crate verifying multiversed works on wasm32-wasip1.
 //!
-//! On wasm32, multiversion elides itself — functions are passthrough.
+//! On wasm32 no clones are generated — functions are passthrough.
 //! This test confirms: compilation succeeds, functions produce correct
 //! results, and simd128 target_feature is detectable at compile time.
 
@@ -0,0 +1,53 @@
+//! Autotune cache tests.
+//!
+//! Kept in their own test binary because they set `MULTIVERSED_AUTOTUNE_CACHE` for
+//! the whole process, and each autotuned function only consults the cache once.
+
+use multiversed::multiversed;
+
+#[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2", autotune)]
+fn sum_cached(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2", autotune)]
+fn sum_precached(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+const LABELS: &[&str] = &["baseline", "x86-64-v4x", "x86-64-v3", "arm64-v2"];
+
+#[test]
+fn test_cache_records_and_reuses_choice() {
+    let path =
+        std::env::temp_dir().join(format!("multiversed-autotune-{}.cache", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    // SAFETY: this is the only test in this binary, so no other thread reads the
+    // environment concurrently.
+    unsafe { std::env::set_var("MULTIVERSED_AUTOTUNE_CACHE", &path) };
+
+    let data: Vec<f32> = (0..1024).map(|i| i as f32).collect();
+    assert!((sum_cached(&data) - 523776.0).abs() < 1.0);
+
+    if !path.exists() {
+        // Nothing is cached without a CPU model to key on (non-x86), or when a
+        // workspace build unifies in `force-disable` and autotune is compiled out.
+        return;
+    }
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    let fields: Vec<&str> = contents.trim_end().split('\t').collect();
+    assert_eq!(fields.len(), 3, "{contents}");
+    assert!(!fields[0].is_empty());
+    assert_eq!(fields[1], "autotune_cache::sum_cached");
+    assert!(LABELS.contains(&fields[2]), "{contents}");
+
+    // A cached choice for this CPU model is used without measuring again.
+    let precached = format!("{}\tautotune_cache::sum_precached\tbaseline\n", fields[0]);
+    let contents = contents + &precached;
+    std::fs::write(&path, &contents).unwrap();
+    assert!((sum_precached(&data) - 523776.0).abs() < 1.0);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), contents);
+
+    std::fs::remove_file(&path).unwrap();
+}
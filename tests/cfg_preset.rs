@@ -0,0 +1,78 @@
+//! Tests for the compile-time `#[cfg_preset]` attribute and `cfg_preset_enabled!`.
+
+use multiversed::{cfg_preset, cfg_preset_enabled};
+
+/// Defines a probe type whose `COMPILED` constant is `true` only if an item gated with
+/// `#[cfg_preset($preset)]` was compiled: the inherent constant shadows the trait's.
+macro_rules! probe {
+    ($probe:ident, $preset:literal) => {
+        struct $probe;
+        impl NotCompiled for $probe {}
+        #[cfg_preset($preset)]
+        impl $probe {
+            const COMPILED: bool = true;
+        }
+    };
+}
+
+trait NotCompiled {
+    const COMPILED: bool = false;
+}
+
+probe!(V2, "x86-64-v2");
+probe!(V3, "x86-64-v3");
+probe!(V4, "x86-64-v4");
+probe!(V4x, "x86-64-v4x");
+probe!(Arm64V2, "arm64");
+probe!(Arm64V3, "arm64-v3");
+probe!(Wasm128, "wasm32-simd128");
+
+#[cfg_preset("x86-64-v3")]
+mod v3_baseline {
+    pub fn uses_avx2() -> bool {
+        cfg!(target_feature = "avx2")
+    }
+}
+
+#[test]
+fn test_attribute_and_predicate_agree() {
+    assert_eq!(V2::COMPILED, cfg_preset_enabled!("x86-64-v2"));
+    assert_eq!(V3::COMPILED, cfg_preset_enabled!("x86-64-v3"));
+    assert_eq!(V4::COMPILED, cfg_preset_enabled!("x86-64-v4"));
+    assert_eq!(V4x::COMPILED, cfg_preset_enabled!("x86-64-v4-modern"));
+    assert_eq!(Arm64V2::COMPILED, cfg_preset_enabled!("arm64-v2"));
+    assert_eq!(Arm64V3::COMPILED, cfg_preset_enabled!("arm64-v3"));
+    assert_eq!(Wasm128::COMPILED, cfg_preset_enabled!("wasm32-simd128"));
+}
+
+#[test]
+fn test_predicate_follows_preset_features() {
+    assert_eq!(
+        cfg_preset_enabled!("x86-64-v3"),
+        cfg!(all(
+            target_arch = "x86_64",
+            target_feature = "avx2",
+            target_feature = "fma",
+            target_feature = "bmi2",
+            target_feature = "movbe",
+            target_feature = "sse4.2"
+        ))
+    );
+    // Presets include their parents' features, so they are enabled together.
+    const {
+        assert!(!cfg_preset_enabled!("x86-64-v4x") || cfg_preset_enabled!("x86-64-v4"));
+        assert!(!cfg_preset_enabled!("x86-64-v4") || cfg_preset_enabled!("x86-64-v3"));
+        assert!(!cfg_preset_enabled!("arm64-v3") || cfg_preset_enabled!("arm64"));
+        #[cfg(not(target_arch = "aarch64"))]
+        assert!(!cfg_preset_enabled!("arm64-v2"));
+        #[cfg(not(target_arch = "x86_64"))]
+        assert!(!cfg_preset_enabled!("x86-64-v2"));
+    }
+}
+
+#[cfg_preset("x86-64-v3")]
+#[test]
+fn test_gated_items_see_the_features() {
+    const { assert!(cfg_preset_enabled!("x86-64-v2")) };
+    assert!(v3_baseline::uses_avx2());
+}
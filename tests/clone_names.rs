@@ -0,0 +1,89 @@
+//! Tests for the names of the generated clones, which show in profiles and backtraces.
+
+use multiversed::multiversed;
+
+/// The path of the clone running this function, from a nested item's type name.
+macro_rules! clone_path {
+    () => {{
+        fn here() {}
+        std::any::type_name_of_val(&here).trim_end_matches("::here")
+    }};
+}
+
+#[multiversed(
+    "x86-64-v4x",
+    "x86-64-v3",
+    "x86_64+avx+popcnt",
+    "arm64-v3",
+    "arm64-v2",
+    companion
+)]
+fn tiered() -> &'static str {
+    clone_path!()
+}
+
+#[multiversed("x86_64+sse+sse2+sse3+ssse3", "aarch64+neon", companion)]
+fn raw() -> &'static str {
+    clone_path!()
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", min_len = 4, len = data, companion)]
+fn short(data: &[u8]) -> &'static str {
+    let _ = data;
+    clone_path!()
+}
+
+/// Clones exist, unless a workspace build unifies in `force-disable`.
+fn cloned() -> bool {
+    !cfg!(feature = "force-disable")
+}
+
+/// The suffix `#[multiversed]` gives the clone of `target`.
+fn suffix(target: &str) -> &str {
+    match target {
+        "x86-64-v4x" => "x86_64_v4x",
+        "x86-64-v3" => "x86_64_v3",
+        "x86_64+avx+popcnt" => "x86_64_avx_popcnt",
+        "arm64-v3" => "arm64_v3",
+        "arm64-v2" => "arm64_v2",
+        "x86_64+sse+sse2+sse3+ssse3" => "x86_64_t57858d01",
+        "aarch64+neon" => "aarch64_neon",
+        "baseline" => "baseline",
+        _ => panic!("unexpected target {target}"),
+    }
+}
+
+#[test]
+fn test_preset_clones_are_named_after_presets() {
+    if !cloned() {
+        assert_eq!(tiered(), "clone_names::tiered");
+        return;
+    }
+    let expected = format!(
+        "clone_names::tiered::tiered__{}",
+        suffix(tiered::selected_target())
+    );
+    assert_eq!(tiered(), expected);
+}
+
+#[test]
+fn test_raw_targets_get_derived_tags() {
+    if !cloned() {
+        return;
+    }
+    let expected = format!("clone_names::raw::raw__{}", suffix(raw::selected_target()));
+    assert_eq!(raw(), expected);
+}
+
+#[test]
+fn test_policy_clones_keep_their_names() {
+    if !cloned() {
+        return;
+    }
+    assert_eq!(short(&[0]), "clone_names::short::short__baseline");
+    let expected = format!(
+        "clone_names::short::__multiversed_tiered::short__{}",
+        suffix(short::selected_target())
+    );
+    assert_eq!(short(&[0; 8]), expected);
+}
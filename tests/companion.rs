@@ -0,0 +1,127 @@
+//! Tests for the companion module generated next to functions with `companion`.
+
+use multiversed::{detect, multiversed};
+
+mod kernels {
+    use multiversed::multiversed;
+
+    #[multiversed("x86-64-v4x", "x86-64-v3", "x86_64+avx+popcnt", "arm64-v2", companion)]
+    pub fn sum(data: &[f32]) -> f32 {
+        data.iter().sum()
+    }
+}
+
+#[multiversed("x86-64-v4", "x86-64-v3", "arm64-v2", min_len = 4, len = data, companion)]
+fn min(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::INFINITY, f32::min)
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", autotune, companion)]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[multiversed("wasm32-simd128", companion)]
+fn no_clones(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+struct Kernels;
+
+impl Kernels {
+    // Without `companion`, associated functions need nothing extra.
+    #[multiversed("x86-64-v3", "arm64-v2")]
+    fn max(data: &[f32]) -> f32 {
+        data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+    }
+
+    #[multiversed("x86-64-v3", "arm64-v2", min_len = 4, len = data)]
+    fn mean(data: &[f32]) -> f32 {
+        data.iter().sum::<f32>() / data.len() as f32
+    }
+}
+
+/// A module sharing the name of the function below, which a companion module would clash with.
+mod product {
+    pub const ONE: f32 = 1.0;
+}
+
+#[multiversed("x86-64-v3", "arm64-v2")]
+fn product(data: &[f32]) -> f32 {
+    data.iter().fold(product::ONE, |acc, x| acc * x)
+}
+
+/// Whether clones exist: not in a workspace build that unifies in `force-disable`.
+fn multiversioned() -> bool {
+    !cfg!(feature = "force-disable")
+}
+
+/// The first of `targets` the host supports, as plain dispatch picks it.
+fn expected(targets: &[&'static str]) -> &'static str {
+    let supported = |target: &str| match target {
+        "x86-64-v4x" => detect!("x86-64-v4x"),
+        "x86-64-v4" => detect!("x86-64-v4"),
+        "x86-64-v3" => detect!("x86-64-v3"),
+        "x86_64+avx+popcnt" => detect!("x86-64-v2") && std::arch::is_x86_feature_detected!("avx"),
+        "arm64-v2" => detect!("arm64-v2"),
+        _ => unreachable!("{target}"),
+    };
+    targets
+        .iter()
+        .copied()
+        .find(|target| supported(target))
+        .unwrap_or("baseline")
+}
+
+#[test]
+fn test_available_targets_are_this_arch() {
+    if !multiversioned() {
+        assert!(kernels::sum::available_targets().is_empty());
+        assert_eq!(kernels::sum::selected_target(), "baseline");
+        return;
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        assert_eq!(
+            kernels::sum::available_targets(),
+            ["x86-64-v4x", "x86-64-v3", "x86_64+avx+popcnt"]
+        );
+        assert_eq!(min::available_targets(), ["x86-64-v4", "x86-64-v3"]);
+    }
+    #[cfg(target_arch = "aarch64")]
+    assert_eq!(kernels::sum::available_targets(), ["arm64-v2"]);
+    assert!(no_clones::available_targets().is_empty());
+}
+
+#[test]
+fn test_selected_target_matches_detection() {
+    let selected = kernels::sum::selected_target();
+    if multiversioned() {
+        assert_eq!(selected, expected(kernels::sum::available_targets()));
+        assert_eq!(min::selected_target(), expected(min::available_targets()));
+    }
+    // Resolving doesn't depend on a call, and the call then agrees.
+    let data: Vec<f32> = (0..64).map(|i| i as f32).collect();
+    assert_eq!(kernels::sum(&data), 2016.0);
+    assert_eq!(kernels::sum::selected_target(), selected);
+    assert_eq!(min(&data), 0.0);
+    assert_eq!(no_clones(&data), 2016.0);
+    assert_eq!(no_clones::selected_target(), "baseline");
+}
+
+#[test]
+fn test_without_companion() {
+    let data: Vec<f32> = (1..=8).map(|i| i as f32).collect();
+    assert_eq!(Kernels::max(&data), 8.0);
+    assert_eq!(Kernels::mean(&data), 4.5);
+    assert_eq!(Kernels::mean(&data[..2]), 1.5);
+    assert_eq!(product(&data), 40320.0);
+}
+
+#[test]
+fn test_autotune_reports_the_tuned_tier() {
+    let data: Vec<f32> = (0..256).map(|i| i as f32).collect();
+    assert_eq!(dot(&data, &data), 5559680.0);
+    let tuned = dot::selected_target();
+    assert!(tuned == "baseline" || dot::available_targets().contains(&tuned));
+}
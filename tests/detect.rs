@@ -0,0 +1,144 @@
+//! Tests for the `detect!` runtime preset check.
+
+use multiversed::{Arch, Preset, detect};
+
+/// std's detection of one preset feature.
+#[cfg(target_arch = "x86_64")]
+fn std_detected(feature: &str) -> bool {
+    use std::arch::is_x86_feature_detected as detected;
+
+    // The macro needs literal names.
+    match feature {
+        "sse" => detected!("sse"),
+        "sse2" => detected!("sse2"),
+        "sse3" => detected!("sse3"),
+        "ssse3" => detected!("ssse3"),
+        "sse4.1" => detected!("sse4.1"),
+        "sse4.2" => detected!("sse4.2"),
+        "popcnt" => detected!("popcnt"),
+        "cmpxchg16b" => detected!("cmpxchg16b"),
+        "pclmulqdq" => detected!("pclmulqdq"),
+        "aes" => detected!("aes"),
+        "avx" => detected!("avx"),
+        "avx2" => detected!("avx2"),
+        "fma" => detected!("fma"),
+        "bmi1" => detected!("bmi1"),
+        "bmi2" => detected!("bmi2"),
+        "f16c" => detected!("f16c"),
+        "lzcnt" => detected!("lzcnt"),
+        "movbe" => detected!("movbe"),
+        "avx512f" => detected!("avx512f"),
+        "avx512bw" => detected!("avx512bw"),
+        "avx512cd" => detected!("avx512cd"),
+        "avx512dq" => detected!("avx512dq"),
+        "avx512vl" => detected!("avx512vl"),
+        "avx512vpopcntdq" => detected!("avx512vpopcntdq"),
+        "avx512ifma" => detected!("avx512ifma"),
+        "avx512vbmi" => detected!("avx512vbmi"),
+        "avx512vbmi2" => detected!("avx512vbmi2"),
+        "avx512bitalg" => detected!("avx512bitalg"),
+        "avx512vnni" => detected!("avx512vnni"),
+        "vpclmulqdq" => detected!("vpclmulqdq"),
+        "gfni" => detected!("gfni"),
+        "vaes" => detected!("vaes"),
+        _ => panic!("{feature} missing from the test"),
+    }
+}
+
+/// std's detection of one preset feature.
+#[cfg(target_arch = "aarch64")]
+fn std_detected(feature: &str) -> bool {
+    use std::arch::is_aarch64_feature_detected as detected;
+
+    match feature {
+        "neon" => detected!("neon"),
+        "crc" => detected!("crc"),
+        "rdm" => detected!("rdm"),
+        "dotprod" => detected!("dotprod"),
+        "fp16" => detected!("fp16"),
+        "aes" => detected!("aes"),
+        "sha2" => detected!("sha2"),
+        "fhm" => detected!("fhm"),
+        "fcma" => detected!("fcma"),
+        "sha3" => detected!("sha3"),
+        "i8mm" => detected!("i8mm"),
+        "bf16" => detected!("bf16"),
+        _ => panic!("{feature} missing from the test"),
+    }
+}
+
+/// std's detection of one preset feature. wasm32 has none at runtime.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+fn std_detected(feature: &str) -> bool {
+    match feature {
+        "simd128" => cfg!(target_feature = "simd128"),
+        _ => panic!("{feature} missing from the test"),
+    }
+}
+
+/// Whether std detects every feature of `preset` on this host.
+fn std_supports(preset: Preset) -> bool {
+    Arch::host() == Some(preset.arch()) && preset.features().iter().all(|f| std_detected(f))
+}
+
+#[test]
+fn test_detect_matches_std() {
+    let detected = [
+        (Preset::X86_64V2, detect!("x86-64-v2")),
+        (Preset::X86_64V3, detect!("x86-64-v3")),
+        (Preset::X86_64V4, detect!("x86-64-v4")),
+        (Preset::X86_64V4x, detect!("x86-64-v4x")),
+        (Preset::Arm64V2, detect!("arm64-v2")),
+        (Preset::Arm64V3, detect!("arm64-v3")),
+        (Preset::Wasm128, detect!("wasm32-simd128")),
+    ];
+    assert_eq!(detected.len(), Preset::ALL.len());
+    for (preset, detected) in detected {
+        assert_eq!(detected, std_supports(preset), "{preset}");
+    }
+}
+
+#[test]
+fn test_detect_aliases() {
+    assert_eq!(detect!("x86-64-v4-modern"), detect!("x86-64-v4x"));
+    assert_eq!(detect!("arm64"), detect!("arm64-v2"));
+}
+
+#[test]
+fn test_detect_other_arch_is_false() {
+    #[cfg(target_arch = "x86_64")]
+    assert!(!detect!("arm64-v2"));
+    #[cfg(target_arch = "aarch64")]
+    assert!(!detect!("x86-64-v2"));
+    #[cfg(not(target_arch = "wasm32"))]
+    assert!(!detect!("wasm32-simd128"));
+}
+
+#[test]
+fn test_detect_tiers_are_ordered() {
+    if detect!("x86-64-v4x") {
+        assert!(detect!("x86-64-v4"));
+    }
+    if detect!("x86-64-v4") {
+        assert!(detect!("x86-64-v3"));
+    }
+    if detect!("x86-64-v3") {
+        assert!(detect!("x86-64-v2"));
+    }
+    if detect!("arm64-v3") {
+        assert!(detect!("arm64-v2"));
+    }
+}
+
+#[test]
+fn test_detect_crate_path() {
+    mod reexport {
+        // Unused off x86_64, where the detection folds to `false`.
+        #[allow(unused_imports)]
+        pub use multiversed as mv;
+    }
+    assert_eq!(
+        detect!("x86-64-v3", crate = "reexport::mv"),
+        detect!("x86-64-v3")
+    );
+}
@@ -0,0 +1,134 @@
+//! Deterministic floating-point tests.
+//!
+//! Each kernel below is multiversioned with `deterministic_fp`, so it runs its tier's
+//! clone when the host supports it and the fallback otherwise. Every tier must produce
+//! bit-identical output to a plain, non-multiversioned reference, and no clone may
+//! enable FMA.
+
+use multiversed::multiversed;
+
+const N: usize = 1031;
+
+fn inputs() -> (Vec<f32>, Vec<f32>) {
+    let a = (0..N).map(|i| (i as f32 * 0.37).sin() * 3.1).collect();
+    let b = (0..N).map(|i| (i as f32 * 0.11).cos() + 0.013).collect();
+    (a, b)
+}
+
+/// Reference kernel: elementwise polynomial followed by a multiply-accumulate.
+fn polynomial_reference(a: &[f32], b: &[f32], out: &mut [f32]) -> f32 {
+    let mut acc = 0.0f32;
+    for ((o, x), y) in out.iter_mut().zip(a).zip(b) {
+        *o = (x * 1.1 + 0.3) * x - y * 0.7;
+        acc += x * y;
+    }
+    acc
+}
+
+// The default `x86-64-v4x` tier is dropped, with a warning.
+#[allow(deprecated)]
+#[multiversed(deterministic_fp)]
+fn polynomial_default(a: &[f32], b: &[f32], out: &mut [f32]) -> f32 {
+    let mut acc = 0.0f32;
+    for ((o, x), y) in out.iter_mut().zip(a).zip(b) {
+        *o = (x * 1.1 + 0.3) * x - y * 0.7;
+        acc += x * y;
+    }
+    acc
+}
+
+#[multiversed(deterministic_fp, "x86-64-v3", "arm64-v2")]
+fn polynomial_v3(a: &[f32], b: &[f32], out: &mut [f32]) -> f32 {
+    let mut acc = 0.0f32;
+    for ((o, x), y) in out.iter_mut().zip(a).zip(b) {
+        *o = (x * 1.1 + 0.3) * x - y * 0.7;
+        acc += x * y;
+    }
+    acc
+}
+
+/// `a * b + c`, fused in clones compiled with FMA, as kernels picking a path by the
+/// clone's features do. `deterministic_fp` must leave every clone on the unfused path.
+#[allow(deprecated)]
+#[multiversed(deterministic_fp, "x86-64-v3", "arm64-v2")]
+fn multiply_add(a: f32, b: f32, c: f32) -> f32 {
+    multiversed::target::match_target! {
+        "x86_64+fma" => a.mul_add(b, c),
+        _ => a * b + c,
+    }
+}
+
+/// Whether the running clone was compiled with FMA.
+#[cfg(target_arch = "x86_64")]
+#[allow(deprecated)]
+#[multiversed(deterministic_fp)]
+fn selected_has_fma() -> bool {
+    multiversed::target::selected_target!().supports_feature_str("fma")
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[test]
+fn test_polynomial_bit_identical() {
+    let (a, b) = inputs();
+    let mut expected = vec![0.0f32; N];
+    let expected_acc = polynomial_reference(&a, &b, &mut expected);
+
+    type Kernel = fn(&[f32], &[f32], &mut [f32]) -> f32;
+    let kernels: &[(&str, Kernel)] = &[
+        ("default", polynomial_default),
+        ("x86-64-v3, arm64-v2", polynomial_v3),
+    ];
+
+    for (name, kernel) in kernels {
+        let mut out = vec![0.0f32; N];
+        let acc = kernel(&a, &b, &mut out);
+        assert_eq!(acc.to_bits(), expected_acc.to_bits(), "{name}: accumulator");
+        for (i, (got, want)) in out.iter().zip(&expected).enumerate() {
+            assert_eq!(got.to_bits(), want.to_bits(), "{name}: element {i}");
+        }
+    }
+}
+
+#[test]
+fn test_multiply_add_unfused() {
+    // (1 + 2^-12)^2 = 1 + 2^-11 + 2^-24: rounding the product drops the 2^-24, fusing
+    // keeps it.
+    let a = 1.0 + f32::EPSILON * 2048.0;
+    let (b, c) = (a, -1.0);
+    let unfused = a * b + c;
+    assert_ne!(unfused.to_bits(), a.mul_add(b, c).to_bits());
+
+    assert_eq!(multiply_add(a, b, c).to_bits(), unfused.to_bits());
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn test_no_clone_has_fma() {
+    assert!(!selected_has_fma());
+}
+
+#[test]
+fn test_dispatched_targets() {
+    let Some(function) = multiversed::registry()
+        .iter()
+        .find(|f| f.path() == "deterministic_fp::polynomial_default")
+    else {
+        // Not registered under `force-disable` or without presets for the host.
+        return;
+    };
+    let targets = function.targets();
+    for target in targets {
+        let preset: multiversed::Preset = target.parse().unwrap();
+        assert!(
+            !preset.features().iter().any(|f| f.starts_with("avx512")),
+            "{target} is an AVX-512 tier"
+        );
+    }
+    // The reduced v3 keeps its preset name.
+    if cfg!(all(target_arch = "x86_64", feature = "x86-64-v3")) {
+        assert!(targets.contains(&"x86-64-v3"), "{targets:?}");
+    }
+}
@@ -0,0 +1,33 @@
+//! Tests that the appended dispatch docs leave functions usable, with and without
+//! `no_dispatch_doc`. The appended line itself only shows in rendered rustdoc.
+
+use multiversed::multiversed;
+
+/// Sum of the elements.
+///
+/// ```
+/// assert_eq!(1 + 1, 2);
+/// ```
+#[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2")]
+fn documented(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[multiversed("x86-64-v3", "arm64-v2")]
+fn undocumented(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+/// Sum of the elements, with its docs kept as written.
+#[multiversed("x86-64-v3", "arm64-v2", no_dispatch_doc)]
+fn opted_out(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[test]
+fn functions_still_dispatch() {
+    let data = [1.0, 2.0, 3.0, 4.0];
+    assert_eq!(documented(&data), 10.0);
+    assert_eq!(undocumented(&data), 10.0);
+    assert_eq!(opted_out(&data), 10.0);
+}
@@ -0,0 +1,114 @@
+//! Tests for calling each tier's clone directly with `#[multiversed(expose)]`.
+
+use multiversed::{Preset, multiversed};
+
+fn weight(i: usize) -> f32 {
+    (i % 7) as f32
+}
+
+#[multiversed(
+    "x86-64-v4x",
+    "x86-64-v3",
+    "x86_64+avx+popcnt",
+    "arm64-v3",
+    "arm64-v2",
+    expose
+)]
+fn weighted_sum(data: &[f32]) -> f32 {
+    data.iter().enumerate().map(|(i, x)| x * weight(i)).sum()
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", min_len = 4, len = data, expose)]
+fn offset_sum((scale, offset): (f32, f32), data: &[f32]) -> f32 {
+    data.iter().map(|x| x * scale + offset).sum()
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", autotune, expose)]
+fn max(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+fn data() -> Vec<f32> {
+    (0..100).map(|i| i as f32 * 0.5).collect()
+}
+
+#[test]
+fn test_baseline_matches_dispatch() {
+    let data = data();
+    assert_eq!(weighted_sum::tiers::baseline(&data), weighted_sum(&data));
+    assert_eq!(
+        offset_sum::tiers::baseline((2.0, 1.0), &data),
+        offset_sum((2.0, 1.0), &data)
+    );
+    assert_eq!(max::tiers::baseline(&data), max(&data));
+}
+
+#[test]
+fn test_try_call_checks_detection() {
+    let data = data();
+    let expected = weighted_sum::tiers::baseline(&data);
+    for preset in Preset::ALL {
+        let cloned = weighted_sum::available_targets().contains(&preset.name());
+        let result = weighted_sum::tiers::try_call(preset, &data);
+        if cloned && preset.is_supported() {
+            assert_eq!(result, Some(expected), "{preset}");
+        } else {
+            assert_eq!(result, None, "{preset}");
+        }
+    }
+
+    // `min_len` only applies to dispatch; `try_call` runs the clone for short inputs too.
+    let cloned = offset_sum::available_targets().contains(&"x86-64-v3");
+    assert_eq!(
+        offset_sum::tiers::try_call(Preset::X86_64V3, (2.0, 1.0), &[1.0, 2.0]),
+        (cloned && Preset::X86_64V3.is_supported()).then_some(8.0)
+    );
+
+    // Each autotuned tier has its own copy.
+    assert_eq!(
+        max::tiers::try_call(Preset::X86_64V3, &[1.0, 3.0, 2.0]),
+        (max::available_targets().contains(&"x86-64-v3") && Preset::X86_64V3.is_supported())
+            .then_some(3.0)
+    );
+}
+
+#[test]
+fn test_tiers_see_the_function_scope() {
+    // Items of the enclosing body, which a module next to `scaled` couldn't name.
+    const SCALE: f32 = 4.0;
+    fn scale(x: f32) -> f32 {
+        x * SCALE
+    }
+
+    #[multiversed("x86-64-v3", "arm64-v2", expose)]
+    fn scaled(data: &[f32]) -> f32 {
+        data.iter().copied().map(scale).sum()
+    }
+
+    let data = data();
+    assert_eq!(scaled::tiers::baseline(&data), scaled(&data));
+    if let Some(sum) = scaled::tiers::try_call(Preset::X86_64V3, &data) {
+        assert_eq!(sum, scaled(&data));
+    }
+    if let Some(sum) = scaled::tiers::try_call(Preset::Arm64V2, &data) {
+        assert_eq!(sum, scaled(&data));
+    }
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", not(feature = "force-disable")))]
+fn test_tiers_are_callable_by_name() {
+    let data = data();
+    let expected = weighted_sum::tiers::baseline(&data);
+    if multiversed::detect!("x86-64-v3") {
+        // SAFETY: the CPU supports x86-64-v3.
+        assert_eq!(unsafe { weighted_sum::tiers::x86_64_v3(&data) }, expected);
+    }
+    if is_x86_feature_detected!("avx") && is_x86_feature_detected!("popcnt") {
+        // SAFETY: the CPU supports avx and popcnt.
+        assert_eq!(
+            unsafe { weighted_sum::tiers::x86_64_avx_popcnt(&data) },
+            expected
+        );
+    }
+}
@@ -0,0 +1,53 @@
+//! Tests for resolving every dispatcher ahead of time with `init_all()`.
+//!
+//! Kept in their own test binary so no function below is called before `init_all()`.
+
+use multiversed::{Arch, Preset, capabilities, init_all, multiversed, registry};
+
+#[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2", companion)]
+#[allow(dead_code)]
+fn sum(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[multiversed("x86-64-v3", "x86_64+avx+popcnt", "arm64-v2", min_len = 4, len = data, companion)]
+#[allow(dead_code)]
+fn max(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", autotune)]
+#[allow(dead_code)]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[test]
+fn test_init_all_resolves_without_calls() {
+    init_all();
+    for function in registry() {
+        let name = function.path().trim_start_matches("init_all::");
+        match name {
+            "sum" => assert_eq!(function.selected(), Some(sum::selected_target())),
+            "max" => assert_eq!(function.selected(), Some(max::selected_target())),
+            // Autotuned functions measure real inputs, so they wait for their first call.
+            "dot" => assert_eq!(function.selected(), None),
+            _ => panic!("unexpected {}", function.path()),
+        }
+    }
+}
+
+#[test]
+fn test_snapshot_agrees_with_presets() {
+    let host = capabilities();
+    for preset in Preset::ALL {
+        assert_eq!(host.supports(preset), preset.is_supported(), "{preset}");
+        // Other architectures can share feature names, such as `aes`.
+        if Some(preset.arch()) != Arch::host() {
+            continue;
+        }
+        for feature in preset.missing_features() {
+            assert!(!host.has(feature), "{preset}: {feature}");
+        }
+    }
+}
@@ -3,7 +3,7 @@
 //! These tests verify that the generated code compiles and runs correctly
 //! on the actual target architecture.
 
-use multiversed::multiversed;
+use multiversed::{Preset, multiversed};
 
 // ============================================================================
 // Basic usage - no arguments (uses cargo feature defaults)
@@ -79,10 +79,10 @@ fn sum_aarch64_dotprod(data: &[f32]) -> f32 {
 }
 
 // ============================================================================
-// wasm32 preset (ignored by multiversion, just passthrough)
+// wasm32 preset (no clones, just passthrough)
 // ============================================================================
 
-// wasm32-simd128 is silently ignored since multiversion doesn't support wasm32
+// wasm32-simd128 is silently ignored since wasm32 has no runtime feature detection
 #[multiversed("wasm32-simd128")]
 fn sum_wasm_simd128(data: &[f32]) -> f32 {
     data.iter().sum()
@@ -158,6 +158,161 @@ fn variance_f32(data: &[f32]) -> f32 {
     data.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / data.len() as f32
 }
 
+// ============================================================================
+// Input-size threshold (min_len)
+// ============================================================================
+
+/// The path of the clone running this function, from a nested item's type name.
+macro_rules! clone_path {
+    () => {{
+        fn here() {}
+        std::any::type_name_of_val(&here).trim_end_matches("::here")
+    }};
+}
+
+#[multiversed(min_len = 64, len = data, companion)]
+fn sum_threshold(data: &[f32]) -> (f32, &'static str) {
+    (data.iter().sum(), clone_path!())
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", min_len = 16, len = b, companion)]
+fn dot_threshold(a: &[f32], b: &[f32]) -> (f32, &'static str) {
+    (a.iter().zip(b).map(|(x, y)| x * y).sum(), clone_path!())
+}
+
+#[multiversed(min_len = 8, len = data, companion)]
+fn scale_threshold((factor, offset): (f32, f32), data: &mut [f32]) -> &'static str {
+    for x in data.iter_mut() {
+        *x = *x * factor + offset;
+    }
+    clone_path!()
+}
+
+#[multiversed(min_len = 32, len = data, companion)]
+fn sum_generic_threshold<T: std::iter::Sum + Copy>(data: &[T]) -> (T, &'static str) {
+    (data.iter().copied().sum(), clone_path!())
+}
+
+/// Check that a `min_len` function ran its fallback below the threshold and the
+/// selected clone at or above it.
+fn assert_threshold_clone(name: &str, selected: &str, path: &str, short: bool) {
+    // No clones when a workspace build unifies in `force-disable`, or without presets
+    // for the host.
+    if cfg!(feature = "force-disable")
+        || !cfg!(any(target_arch = "x86_64", target_arch = "aarch64"))
+    {
+        assert_eq!(path, format!("integration::{name}"));
+        return;
+    }
+    let expected = if short {
+        format!("integration::{name}::{name}__baseline")
+    } else {
+        let suffix = selected.replace(['-', '.'], "_");
+        format!("integration::{name}::__multiversed_tiered::{name}__{suffix}")
+    };
+    assert_eq!(
+        path,
+        expected,
+        "{}",
+        if short { "below min_len" } else { "at min_len" }
+    );
+}
+
+// ============================================================================
+// Microarchitecture policy (avoid_on)
+// ============================================================================
+
+#[multiversed(avoid_on = ["zen4", "skylake-x"], companion)]
+fn sum_avoid_wide(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[multiversed(
+    "x86-64-v4x",
+    "x86-64-v4",
+    "x86-64-v3",
+    "arm64-v2",
+    avoid_on = ["zen4", "zen5", "skylake-x", "icelake"],
+    min_len = 16,
+    len = data,
+    companion
+)]
+fn sum_avoid_wide_threshold(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+// ============================================================================
+// First-call autotuning (autotune)
+// ============================================================================
+
+#[multiversed(autotune)]
+fn sum_autotune(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[multiversed(autotune, min_len = 16, len = data)]
+fn sum_autotune_threshold(data: &[i64]) -> i64 {
+    data.iter().sum()
+}
+
+// Rerunning the first call would scale the caller's data again, so the mutable
+// argument needs a sample.
+fn scale_sample(run: &mut dyn FnMut(&mut [f32], f32)) {
+    run(&mut [1.0; 4096], 1.5);
+}
+
+#[multiversed(
+    "x86-64-v4x",
+    "x86-64-v3",
+    "arm64-v3",
+    "arm64-v2",
+    autotune = scale_sample
+)]
+fn scale_autotune(data: &mut [f32], factor: f32) {
+    for x in data.iter_mut() {
+        *x *= factor;
+    }
+}
+
+fn dot_sample(run: &mut dyn FnMut(&[f32], &[f32])) {
+    let a = vec![0.5f32; 4096];
+    let b = vec![2.0f32; 4096];
+    run(&a, &b);
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", autotune = dot_sample)]
+fn dot_autotune(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+// The items each clone starts with must not trip lints when the body is a one-line
+// expression.
+#[rustfmt::skip]
+#[multiversed("x86-64-v3", "arm64-v2")]
+fn doubled(x: f32) -> f32 { x * 2.0 }
+
+// ============================================================================
+// In-body helpers
+// ============================================================================
+
+// They also work in the fallback, on other architectures and with `force-disable`.
+
+// `supports_feature_str` panics on features of another architecture.
+#[cfg(target_arch = "x86_64")]
+#[multiversed("x86-64-v3", "arm64-v2", companion)]
+fn selected_has_avx2() -> bool {
+    multiversed::target::selected_target!().supports_feature_str("avx2")
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", companion)]
+fn matched_tier() -> &'static str {
+    multiversed::target::match_target! {
+        "x86_64+avx2+fma" => "avx2",
+        "aarch64+neon+dotprod" => "dotprod",
+        _ => "other",
+    }
+}
+
 // ============================================================================
 // Tests
 // ============================================================================
@@ -176,6 +331,11 @@ fn test_public_sum() {
     assert!((result - 6.0).abs() < 0.001);
 }
 
+#[test]
+fn test_single_expression_body() {
+    assert_eq!(doubled(1.5), 3.0);
+}
+
 #[test]
 fn test_x86_presets() {
     let data = [1.0f32, 2.0, 3.0, 4.0];
@@ -298,3 +458,155 @@ fn test_large_dot_product() {
     let expected: f32 = (0..1024).map(|i| (i * 2 * i) as f32).sum();
     assert!((result - expected).abs() < 1000.0);
 }
+
+#[test]
+fn test_threshold_sum() {
+    for len in [0, 1, 63, 64, 65, 1024] {
+        let data: Vec<f32> = (0..len).map(|i| i as f32).collect();
+        let expected: f32 = data.iter().sum();
+        let (sum, path) = sum_threshold(&data);
+        assert_eq!(sum, expected, "len {len}");
+        assert_threshold_clone(
+            "sum_threshold",
+            sum_threshold::selected_target(),
+            path,
+            len < 64,
+        );
+    }
+}
+
+#[test]
+fn test_threshold_dot_product() {
+    for len in [4, 15, 16, 100] {
+        let a: Vec<f32> = (0..len).map(|i| i as f32).collect();
+        let b: Vec<f32> = (0..len).map(|i| (i % 3) as f32).collect();
+        let expected: f32 = a.iter().zip(&b).map(|(x, y)| x * y).sum();
+        let (dot, path) = dot_threshold(&a, &b);
+        assert_eq!(dot, expected, "len {len}");
+        assert_threshold_clone(
+            "dot_threshold",
+            dot_threshold::selected_target(),
+            path,
+            len < 16,
+        );
+    }
+}
+
+#[test]
+fn test_threshold_pattern_argument() {
+    let selected = scale_threshold::selected_target();
+
+    let mut short = [1.0f32; 7];
+    let path = scale_threshold((2.0, 1.0), &mut short);
+    assert_eq!(short, [3.0; 7]);
+    assert_threshold_clone("scale_threshold", selected, path, true);
+
+    let mut long = [1.0f32; 8];
+    let path = scale_threshold((2.0, 1.0), &mut long);
+    assert_eq!(long, [3.0; 8]);
+    assert_threshold_clone("scale_threshold", selected, path, false);
+}
+
+#[test]
+fn test_threshold_generic() {
+    let selected = sum_generic_threshold::selected_target();
+
+    let short: Vec<i32> = (0..31).collect();
+    let (sum, path) = sum_generic_threshold(&short);
+    assert_eq!(sum, 465);
+    assert_threshold_clone("sum_generic_threshold", selected, path, true);
+
+    let long: Vec<i64> = (0..32).collect();
+    let (sum, path) = sum_generic_threshold(&long);
+    assert_eq!(sum, 496);
+    assert_threshold_clone("sum_generic_threshold", selected, path, false);
+}
+
+#[test]
+fn test_avoid_on() {
+    let data: Vec<f32> = (0..1024).map(|i| i as f32).collect();
+    assert!((sum_avoid_wide(&data) - 523776.0).abs() < 1.0);
+    assert!((sum_avoid_wide_threshold(&data) - 523776.0).abs() < 1.0);
+    assert_eq!(sum_avoid_wide_threshold(&data[..4]), 6.0);
+}
+
+/// The tier a function with these targets should pick: the first the CPU supports,
+/// skipping the AVX-512 ones when the CPU is on the function's `avoid_on` list.
+fn expected_tier(available: &[&'static str], avoid_on: &[&str]) -> &'static str {
+    let avoided = multiversed::__private::on_microarch(avoid_on);
+    available
+        .iter()
+        .copied()
+        .find(|target| {
+            let preset: Preset = target.parse().unwrap();
+            preset.is_supported()
+                && !(avoided && preset.features().iter().any(|f| f.starts_with("avx512")))
+        })
+        .unwrap_or("baseline")
+}
+
+#[test]
+fn test_avoid_on_selects_tier() {
+    assert_eq!(
+        sum_avoid_wide::selected_target(),
+        expected_tier(sum_avoid_wide::available_targets(), &["zen4", "skylake-x"])
+    );
+    assert_eq!(
+        sum_avoid_wide_threshold::selected_target(),
+        expected_tier(
+            sum_avoid_wide_threshold::available_targets(),
+            &["zen4", "zen5", "skylake-x", "icelake"]
+        )
+    );
+}
+
+#[test]
+fn test_autotune_first_call() {
+    let data: Vec<f32> = (0..1024).map(|i| i as f32).collect();
+    for _ in 0..3 {
+        assert!((sum_autotune(&data) - 523776.0).abs() < 1.0);
+    }
+}
+
+#[test]
+fn test_autotune_mutable_argument() {
+    // Measuring runs on the sample, so the first call scales the data once.
+    let mut data = [1.0f32; 64];
+    scale_autotune(&mut data, 3.0);
+    assert_eq!(data, [3.0; 64]);
+    scale_autotune(&mut data, 3.0);
+    assert_eq!(data, [9.0; 64]);
+}
+
+#[test]
+fn test_helpers_see_the_clone() {
+    #[cfg(target_arch = "x86_64")]
+    {
+        let v3 = selected_has_avx2::selected_target() == "x86-64-v3";
+        assert_eq!(selected_has_avx2(), v3 || cfg!(target_feature = "avx2"));
+    }
+
+    let expected = match matched_tier::selected_target() {
+        "x86-64-v3" => "avx2",
+        "arm64-v2" => "dotprod",
+        _ if cfg!(all(target_feature = "avx2", target_feature = "fma")) => "avx2",
+        _ if cfg!(target_feature = "dotprod") => "dotprod",
+        _ => "other",
+    };
+    assert_eq!(matched_tier(), expected);
+}
+
+#[test]
+fn test_autotune_threshold() {
+    assert_eq!(sum_autotune_threshold(&[1, 2, 3]), 6);
+    let long: Vec<i64> = (0..100).collect();
+    assert_eq!(sum_autotune_threshold(&long), 4950);
+}
+
+#[test]
+fn test_autotune_sample() {
+    let a: Vec<f32> = (0..100).map(|i| i as f32).collect();
+    let b = vec![2.0f32; 100];
+    assert_eq!(dot_autotune(&a, &b), 9900.0);
+    assert_eq!(dot_autotune(&a[..3], &b[..3]), 6.0);
+}
@@ -0,0 +1,41 @@
+//! Tests for the `targets!` and `features!` preset string macros.
+
+use multiversed::{Preset, features, targets};
+
+const V3_TARGETS: &str = targets!("x86-64-v3");
+const V3_FEATURES: &str = features!("x86-64-v3");
+
+#[test]
+fn test_targets_match_presets() {
+    assert_eq!(
+        V3_TARGETS,
+        "x86_64+sse+sse2+sse3+ssse3+sse4.1+sse4.2+popcnt+cmpxchg16b+avx+avx2+fma+bmi1+bmi2+f16c+lzcnt+movbe"
+    );
+    assert_eq!(targets!("arm64"), targets!("arm64-v2"));
+    assert_eq!(targets!("x86-64-v4-modern"), targets!("x86-64-v4x"));
+    assert_eq!(targets!("wasm32-simd128"), "wasm32+simd128");
+
+    let expected = |preset: Preset| {
+        format!(
+            "{}+{}",
+            preset.arch().target_arch(),
+            preset.features().join("+")
+        )
+    };
+    assert_eq!(targets!("x86-64-v2"), expected(Preset::X86_64V2));
+    assert_eq!(targets!("x86-64-v4"), expected(Preset::X86_64V4));
+    assert_eq!(targets!("x86-64-v4x"), expected(Preset::X86_64V4x));
+    assert_eq!(targets!("arm64-v3"), expected(Preset::Arm64V3));
+}
+
+#[test]
+fn test_features_match_presets() {
+    assert_eq!(V3_FEATURES, Preset::X86_64V3.features().join(","));
+    assert_eq!(
+        features!("x86-64-v4x"),
+        Preset::X86_64V4x.features().join(",")
+    );
+    assert_eq!(features!("arm64-v2"), "neon,crc,rdm,dotprod,fp16,aes,sha2");
+    assert_eq!(features!("wasm32-simd128"), "simd128");
+    assert!(!features!("x86-64-v3").contains('+'));
+}
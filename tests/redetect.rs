@@ -0,0 +1,75 @@
+//! Tests for resolving dispatchers again with `redetect()`.
+//!
+//! Kept in their own test binary, since redetecting resets every function in it.
+
+use multiversed::{
+    Function, multiversed, redetect, redetect_checkpoint, registry, set_redetect_hook,
+};
+
+#[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2")]
+fn sum(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", autotune)]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn call_all() {
+    let data = [1.0, 2.0, 3.0];
+    assert_eq!(sum(&data), 6.0);
+    assert_eq!(dot(&data, &data), 14.0);
+}
+
+/// The tiers the functions resolved to. `dot` is only checked for having one, since
+/// tuning again may pick another tier when the timings are close.
+fn selected() -> Vec<Option<&'static str>> {
+    registry()
+        .iter()
+        .map(|function| match function.path() {
+            "redetect::dot" => function.selected().map(|_| "tuned"),
+            _ => function.selected(),
+        })
+        .collect()
+}
+
+/// Whether the function's best target is enabled at compile time, so it never detects.
+fn compiled_in(function: &Function) -> bool {
+    function.path() == "redetect::sum" && multiversed::cfg_preset_enabled!("x86-64-v4x")
+}
+
+#[test]
+fn test_redetect_resolves_again() {
+    call_all();
+    let before = selected();
+    assert!(before.iter().all(Option::is_some));
+
+    redetect();
+    for function in registry() {
+        if !compiled_in(function) {
+            assert_eq!(function.selected(), None, "{}", function.path());
+        }
+    }
+
+    // The host didn't change, so every function resolves as before.
+    call_all();
+    assert_eq!(selected(), before);
+
+    // Same host: the default probe sees no change.
+    assert!(!redetect_checkpoint());
+    assert_eq!(selected(), before);
+
+    set_redetect_hook(Some(|| true));
+    assert!(redetect_checkpoint());
+    for function in registry() {
+        if !compiled_in(function) {
+            assert_eq!(function.selected(), None, "{}", function.path());
+        }
+    }
+    set_redetect_hook(Some(|| false));
+    call_all();
+    assert!(!redetect_checkpoint());
+    assert_eq!(selected(), before);
+    set_redetect_hook(None);
+}
@@ -0,0 +1,127 @@
+//! Tests for the process-wide registry of multiversioned functions.
+//!
+//! Kept in their own test binary so the registry lists only the functions below.
+
+use multiversed::{Function, cfg_preset_enabled, detect, multiversed, registry};
+
+#[multiversed("x86-64-v4x", "x86-64-v3", "arm64-v2")]
+fn sum(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+#[multiversed("x86-64-v3", "x86_64+avx+popcnt", "arm64-v2", "aarch64+neon")]
+fn max(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::NEG_INFINITY, f32::max)
+}
+
+#[multiversed("x86-64-v4", "x86-64-v3", "arm64-v2", min_len = 4, len = data)]
+fn min(data: &[f32]) -> f32 {
+    data.iter().copied().fold(f32::INFINITY, f32::min)
+}
+
+#[multiversed("x86-64-v3", "arm64-v2", autotune)]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[multiversed("x86-64-v3", "arm64-v2")]
+#[allow(dead_code)]
+fn never_called(data: &[f32]) -> f32 {
+    data.iter().sum()
+}
+
+fn find(name: &str) -> &'static Function {
+    let path = format!("registry::{name}");
+    let mut matches = registry().iter().filter(|f| f.path() == path);
+    let function = matches
+        .next()
+        .unwrap_or_else(|| panic!("{path} is not registered"));
+    assert!(matches.next().is_none(), "{path} is registered twice");
+    function
+}
+
+/// Clones exist, so functions register, unless a workspace build unifies in
+/// `force-disable` or the host has no presets.
+fn registers() -> bool {
+    !cfg!(feature = "force-disable") && cfg!(any(target_arch = "x86_64", target_arch = "aarch64"))
+}
+
+#[test]
+fn test_lists_every_function_once() {
+    if !registers() {
+        assert!(registry().is_empty());
+        return;
+    }
+    let mut paths: Vec<&str> = registry().iter().map(Function::path).collect();
+    paths.sort_unstable();
+    assert_eq!(
+        paths,
+        [
+            "registry::dot",
+            "registry::max",
+            "registry::min",
+            "registry::never_called",
+            "registry::sum"
+        ]
+    );
+}
+
+#[test]
+fn test_targets_are_labelled_for_this_arch() {
+    if !registers() {
+        return;
+    }
+    #[cfg(target_arch = "x86_64")]
+    {
+        assert_eq!(find("sum").targets(), ["x86-64-v4x", "x86-64-v3"]);
+        assert_eq!(find("max").targets(), ["x86-64-v3", "x86_64+avx+popcnt"]);
+        assert_eq!(find("min").targets(), ["x86-64-v4", "x86-64-v3"]);
+        assert_eq!(find("dot").targets(), ["x86-64-v3"]);
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        assert_eq!(find("sum").targets(), ["arm64-v2"]);
+        assert_eq!(find("max").targets(), ["arm64-v2", "aarch64+neon"]);
+    }
+}
+
+#[test]
+fn test_selected_follows_dispatch() {
+    if !registers() {
+        return;
+    }
+    let data: Vec<f32> = (0..64).map(|i| i as f32).collect();
+    assert_eq!(sum(&data), 2016.0);
+    let expected = if detect!("x86-64-v4x") {
+        "x86-64-v4x"
+    } else if detect!("x86-64-v3") {
+        "x86-64-v3"
+    } else if detect!("arm64-v2") {
+        "arm64-v2"
+    } else {
+        "baseline"
+    };
+    assert_eq!(find("sum").selected(), Some(expected));
+
+    // The fallback for short inputs doesn't resolve the dispatcher.
+    let before = find("min").selected();
+    assert_eq!(min(&data[..2]), 0.0);
+    assert_eq!(find("min").selected(), before);
+    assert_eq!(min(&data), 0.0);
+    assert!(find("min").selected().is_some());
+
+    assert_eq!(max(&data), 63.0);
+    assert!(find("max").selected().is_some());
+
+    assert_eq!(dot(&data, &data), 85344.0);
+    let tuned = find("dot").selected().unwrap();
+    assert!(tuned == "baseline" || find("dot").targets().contains(&tuned));
+
+    // Resolved without a call only if the best target is compiled in.
+    let compiled_in = [
+        cfg_preset_enabled!("x86-64-v3"),
+        cfg_preset_enabled!("arm64-v2"),
+    ]
+    .contains(&true);
+    assert_eq!(find("never_called").selected().is_some(), compiled_in);
+}
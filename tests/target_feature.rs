@@ -0,0 +1,73 @@
+//! Tests for `#[multiversed::target_feature]` on hand-written kernels.
+
+use multiversed::Preset;
+
+#[cfg(target_arch = "x86_64")]
+#[multiversed::target_feature("x86-64-v3")]
+unsafe fn add8_avx2(a: &[f32; 8], b: &[f32; 8]) -> [f32; 8] {
+    use core::arch::x86_64::*;
+
+    let mut out = [0.0f32; 8];
+    // SAFETY: the arrays hold 8 floats each; unaligned loads and stores.
+    unsafe {
+        let sum = _mm256_add_ps(_mm256_loadu_ps(a.as_ptr()), _mm256_loadu_ps(b.as_ptr()));
+        _mm256_storeu_ps(out.as_mut_ptr(), sum);
+    }
+    out
+}
+
+#[cfg(target_arch = "aarch64")]
+#[multiversed::target_feature("arm64-v2")]
+unsafe fn add4_neon(a: &[f32; 4], b: &[f32; 4]) -> [f32; 4] {
+    use core::arch::aarch64::*;
+
+    let mut out = [0.0f32; 4];
+    // SAFETY: the arrays hold 4 floats each.
+    unsafe {
+        vst1q_f32(
+            out.as_mut_ptr(),
+            vaddq_f32(vld1q_f32(a.as_ptr()), vld1q_f32(b.as_ptr())),
+        )
+    };
+    out
+}
+
+/// One preset per architecture; only the host's applies.
+#[multiversed::target_feature("x86-64-v4x", "arm64-v3", "wasm32-simd128")]
+fn sum_wide(data: &[u32]) -> u32 {
+    data.iter().sum()
+}
+
+#[test]
+#[cfg(target_arch = "x86_64")]
+fn test_x86_kernel() {
+    if !Preset::X86_64V3.is_supported() {
+        return;
+    }
+    let a = [1.0f32; 8];
+    let b: [f32; 8] = core::array::from_fn(|i| i as f32);
+    // SAFETY: x86-64-v3 was detected above.
+    let out = unsafe { add8_avx2(&a, &b) };
+    assert_eq!(out, [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+}
+
+#[test]
+#[cfg(target_arch = "aarch64")]
+fn test_aarch64_kernel() {
+    if !Preset::Arm64V2.is_supported() {
+        return;
+    }
+    // SAFETY: arm64-v2 was detected above.
+    let out = unsafe { add4_neon(&[1.0; 4], &[1.0, 2.0, 3.0, 4.0]) };
+    assert_eq!(out, [2.0, 3.0, 4.0, 5.0]);
+}
+
+#[test]
+fn test_multi_arch_kernel() {
+    let presets = [Preset::X86_64V4x, Preset::Arm64V3, Preset::Wasm128];
+    if !presets.into_iter().any(Preset::is_supported) {
+        return;
+    }
+    // SAFETY: the host supports the preset for its architecture, checked above.
+    assert_eq!(unsafe { sum_wide(&[1, 2, 3, 4]) }, 10);
+}
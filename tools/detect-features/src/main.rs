@@ -29,6 +29,27 @@ fn main() {
     println!("No feature detection available for this architecture.");
 }
 
+// =============================================================================
+// Presets
+// =============================================================================
+
+/// Print whether the host supports each multiversed preset for `arch`.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64"))]
+fn print_preset_summary(arch: multiversed_rt::Arch) {
+    use multiversed_rt::Preset;
+
+    println!("### Preset Level Summary");
+    for preset in Preset::ALL.into_iter().filter(|p| p.arch() == arch) {
+        let label = format!("{preset} ({}):", preset.archmage_token());
+        let status = if preset.is_supported() { "✓" } else { "✗" };
+        println!("  {label:<32} {status}");
+        let missing = preset.missing_features();
+        if !missing.is_empty() {
+            println!("    missing: {}", missing.join(", "));
+        }
+    }
+}
+
 // =============================================================================
 // x86/x86_64 - All features from is_x86_feature_detected! documentation
 // =============================================================================
@@ -248,30 +269,8 @@ fn detect_x86_features() {
     print_features("Other", OTHER_FEATURES);
     // Note: AVX10 and AMX features require nightly Rust - omitted from stable builds
 
-    // Microarch levels
-    println!("### Microarchitecture Level Summary");
-    let has_v2 = is_detected("sse4.2") && is_detected("popcnt");
-    let has_v3 = has_v2
-        && is_detected("avx2")
-        && is_detected("fma")
-        && is_detected("bmi1")
-        && is_detected("bmi2");
-    let has_v4 = has_v3
-        && is_detected("avx512f")
-        && is_detected("avx512bw")
-        && is_detected("avx512dq")
-        && is_detected("avx512vl")
-        && is_detected("avx512cd");
-
-    println!(
-        "  x86-64-v2 (SSE4.2+POPCNT): {}",
-        if has_v2 { "✓" } else { "✗" }
-    );
-    println!(
-        "  x86-64-v3 (AVX2+FMA+BMI): {}",
-        if has_v3 { "✓" } else { "✗" }
-    );
-    println!("  x86-64-v4 (AVX-512): {}", if has_v4 { "✓" } else { "✗" });
+    // Preset levels (matching archmage token-registry.toml)
+    print_preset_summary(multiversed_rt::Arch::X86_64);
     println!();
 
     // Target string
@@ -512,33 +511,7 @@ fn detect_aarch64_features() {
     print_features("Other", OTHER_FEATURES);
 
     // Preset levels (matching archmage token-registry.toml)
-    println!("### Preset Level Summary");
-
-    // arm64 / arm64-v2: Arm64V2Token (neon+crc+rdm+dotprod+fp16+aes+sha2)
-    let has_v2 = is_detected("neon")
-        && is_detected("crc")
-        && is_detected("rdm")
-        && is_detected("dotprod")
-        && is_detected("fp16")
-        && is_detected("aes")
-        && is_detected("sha2");
-
-    // arm64-v3: Arm64V3Token (v2 + fhm+fcma+sha3+i8mm+bf16)
-    let has_v3 = has_v2
-        && is_detected("fhm")
-        && is_detected("fcma")
-        && is_detected("sha3")
-        && is_detected("i8mm")
-        && is_detected("bf16");
-
-    println!(
-        "  arm64/arm64-v2 (Arm64V2Token): {}",
-        if has_v2 { "✓" } else { "✗" }
-    );
-    println!(
-        "  arm64-v3 (Arm64V3Token):       {}",
-        if has_v3 { "✓" } else { "✗" }
-    );
+    print_preset_summary(multiversed_rt::Arch::Aarch64);
     println!();
     println!("  For additional features (sve, sve2, etc.),");
     println!("  use raw target strings: \"aarch64+neon+sve2\"");
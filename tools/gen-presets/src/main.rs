@@ -0,0 +1,237 @@
+//! Preset table generator
+//!
+//! Reads the vendored copy of archmage's token-registry.toml and regenerates the preset
+//! table in `multiversed-rt/src/preset_table.rs`, which the macro, `Preset` and the
+//! detector all build on. The test below fails when the checked-in table drifts from
+//! the registry.
+//!
+//! To update, copy `token-registry.toml` unchanged from the archmage release
+//! multiversed requires (currently 0.9.30), run `cargo run -p gen-presets`, and review
+//! the diff of `multiversed-rt/src/preset_table.rs`.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+fn main() {
+    let registry = std::fs::read_to_string(registry_path()).expect("read token-registry.toml");
+    let table = match parse_registry(&registry).and_then(|tokens| generate(&tokens)) {
+        Ok(table) => table,
+        Err(err) => {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+    };
+    std::fs::write(table_path(), table).expect("write preset_table.rs");
+    println!("wrote multiversed-rt/src/preset_table.rs");
+}
+
+fn registry_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("token-registry.toml")
+}
+
+fn table_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("../../multiversed-rt/src/preset_table.rs")
+}
+
+// =============================================================================
+// Preset metadata
+// =============================================================================
+
+/// What multiversed adds to a registry token: the preset's names and its description.
+struct Meta {
+    token: &'static str,
+    name: &'static str,
+    aliases: &'static [&'static str],
+    comment: &'static str,
+}
+
+/// Every preset, least capable first within each architecture. The parent of a preset
+/// is the one before it with the same architecture.
+const PRESETS: &[Meta] = &[
+    Meta {
+        token: "X64V2Token",
+        name: "x86-64-v2",
+        aliases: &[],
+        comment: "x86-64-v2: SSE4.2 + POPCNT + CX16 (Nehalem 2008+, Bulldozer 2011+)",
+    },
+    Meta {
+        token: "X64V3Token",
+        name: "x86-64-v3",
+        aliases: &[],
+        comment: "x86-64-v3: AVX2 + FMA (Haswell 2013+, Zen 1 2017+)",
+    },
+    Meta {
+        token: "X64V4Token",
+        name: "x86-64-v4",
+        aliases: &[],
+        comment: "x86-64-v4: AVX-512 (Skylake-X 2017+, Zen 4 2022+) — psABI v4 (F+CD+VL+DQ+BW) plus AES and PCLMULQDQ",
+    },
+    Meta {
+        token: "X64V4xToken",
+        name: "x86-64-v4x",
+        aliases: &["x86-64-v4-modern"],
+        comment: "x86-64-v4x: Full modern AVX-512 (Ice Lake 2019+, Zen 4 2022+) — adds VNNI, VBMI2, BITALG, GFNI, VAES, VPCLMULQDQ",
+    },
+    Meta {
+        token: "Arm64V2Token",
+        name: "arm64-v2",
+        aliases: &["arm64"],
+        comment: "arm64-v2: Modern ARM baseline (Cortex-A55+, Apple M1+, Graviton 2+). \"arm64\" is an alias for backwards compatibility.",
+    },
+    Meta {
+        token: "Arm64V3Token",
+        name: "arm64-v3",
+        aliases: &[],
+        comment: "arm64-v3: Full modern ARM SIMD (Cortex-A510+, Apple M2+, Graviton 3+)",
+    },
+    Meta {
+        token: "Wasm128Token",
+        name: "wasm32-simd128",
+        aliases: &[],
+        comment: "wasm32-simd128: no runtime detection, so the attribute generates no clones for it",
+    },
+];
+
+// =============================================================================
+// Registry parsing
+// =============================================================================
+
+/// The parts of the registry the table is built from.
+#[derive(Debug, Deserialize)]
+struct Registry {
+    token: Vec<Token>,
+}
+
+/// A `[[token]]` entry of the registry. Other keys are ignored.
+#[derive(Debug, Deserialize)]
+struct Token {
+    name: String,
+    arch: String,
+    features: Vec<String>,
+}
+
+/// Parse the registry's `[[token]]` entries.
+fn parse_registry(text: &str) -> Result<Vec<Token>, String> {
+    toml::from_str::<Registry>(text)
+        .map(|registry| registry.token)
+        .map_err(|err| format!("token-registry.toml: {err}"))
+}
+
+// =============================================================================
+// Table generation
+// =============================================================================
+
+/// Generate `preset_table.rs` from the registry tokens.
+fn generate(tokens: &[Token]) -> Result<String, String> {
+    let mut out = String::from(
+        "// @generated by tools/gen-presets from tools/gen-presets/token-registry.toml.\n\
+         // Do not edit; update the registry snapshot and run `cargo run -p gen-presets`.\n\
+         \n\
+         /// Every preset, least capable first within each architecture.\n\
+         ///\n\
+         /// The order matches the variants of [`Preset`](crate::Preset).\n\
+         pub const PRESETS: &[PresetDef] = &[\n",
+    );
+
+    for (i, meta) in PRESETS.iter().enumerate() {
+        let token = find_token(tokens, meta.token)?;
+        // archmage files the x86-64 levels under "x86" and simd128 under "wasm".
+        let arch = match token.arch.as_str() {
+            "x86" | "x86_64" => "X86_64",
+            "aarch64" => "Aarch64",
+            "wasm" | "wasm32" => "Wasm32",
+            other => return Err(format!("{}: unknown arch `{other}`", token.name)),
+        };
+        // Earlier presets were already found, so their lookups cannot fail here.
+        let parent = PRESETS[..i]
+            .iter()
+            .rev()
+            .filter_map(|meta| Some((meta, find_token(tokens, meta.token).ok()?)))
+            .find(|(_, parent)| parent.arch == token.arch);
+        let (parent_name, adds) = match parent {
+            Some((parent_meta, parent)) => {
+                if let Some(missing) = parent.features.iter().find(|f| !token.features.contains(f))
+                {
+                    return Err(format!(
+                        "{} lacks `{missing}` from {}, so it cannot extend it",
+                        token.name, parent.name
+                    ));
+                }
+                let adds: Vec<&String> = token
+                    .features
+                    .iter()
+                    .filter(|f| !parent.features.contains(f))
+                    .collect();
+                (format!("Some({:?})", parent_meta.name), adds)
+            }
+            None => ("None".to_string(), token.features.iter().collect()),
+        };
+
+        writeln!(out, "    // {}", meta.comment).unwrap();
+        writeln!(out, "    PresetDef {{").unwrap();
+        writeln!(out, "        name: {:?},", meta.name).unwrap();
+        writeln!(out, "        aliases: &{:?},", meta.aliases).unwrap();
+        writeln!(out, "        arch: Arch::{arch},").unwrap();
+        writeln!(out, "        parent: {parent_name},").unwrap();
+        writeln!(out, "        adds: &[").unwrap();
+        for feature in adds {
+            writeln!(out, "            {feature:?},").unwrap();
+        }
+        writeln!(out, "        ],").unwrap();
+        writeln!(out, "        token: {:?},", meta.token).unwrap();
+        writeln!(out, "    }},").unwrap();
+    }
+    out.push_str("];\n");
+    Ok(out)
+}
+
+fn find_token<'a>(tokens: &'a [Token], name: &str) -> Result<&'a Token, String> {
+    tokens
+        .iter()
+        .find(|token| token.name == name)
+        .ok_or_else(|| format!("token-registry.toml has no `{name}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checked_in_table_matches_registry() {
+        let registry = std::fs::read_to_string(registry_path()).unwrap();
+        let expected = generate(&parse_registry(&registry).unwrap()).unwrap();
+        let actual = std::fs::read_to_string(table_path()).unwrap();
+        assert!(
+            actual == expected,
+            "multiversed-rt/src/preset_table.rs is out of date with token-registry.toml; \
+             run `cargo run -p gen-presets`"
+        );
+    }
+
+    #[test]
+    fn rejects_a_token_that_drops_a_parent_feature() {
+        let registry = r#"
+            [[token]]
+            name = "X64V2Token"
+            arch = "x86_64"
+            features = ["sse", "sse2"]  # trailing comment
+
+            [[token]]
+            name = "X64V3Token"
+            arch = "x86_64"
+            features = [
+                "sse",
+                "avx2",
+            ]
+        "#;
+        let tokens = parse_registry(registry).unwrap();
+        assert_eq!(tokens[1].features, ["sse", "avx2"]);
+        let err = generate(&tokens).unwrap_err();
+        assert_eq!(
+            err,
+            "X64V3Token lacks `sse2` from X64V2Token, so it cannot extend it"
+        );
+    }
+}